@@ -0,0 +1,29 @@
+#!/bin/sh
+# Check that the `GeometryNote` section of the `geometry-note` feature is in the ELF file
+# for tools to read, but not in any loadable segment, so a host never copies it to the
+# target.
+set -e
+
+TARGET=${TARGET:-thumbv7em-none-eabi}
+export RUSTFLAGS="-C link-arg=-Tmemory.x"
+TARGET_DIR=target/geometry-note
+ELF=$TARGET_DIR/$TARGET/debug/examples/stm32f4
+
+cargo build --quiet --target "$TARGET" --target-dir "$TARGET_DIR" --example stm32f4 --features geometry-note
+
+if ! readelf -SW "$ELF" | grep -q ' GeometryNote '; then
+    echo "stm32f4 has no GeometryNote section"
+    exit 1
+fi
+# Every segment that holds a section is listed by index in the mapping, match those of
+# the LOAD segments.
+loaded=$(readelf -lW "$ELF" | awk '
+    /^ *LOAD / { load[n++] = 1; next }
+    /^ *[A-Z_]+ +0x/ { load[n++] = 0; next }
+    /^ *[0-9]+ / { if (load[$1 + 0]) print }
+')
+if echo "$loaded" | grep -q 'GeometryNote'; then
+    echo "GeometryNote is in a LOAD segment:"
+    echo "$loaded"
+    exit 1
+fi
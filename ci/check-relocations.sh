@@ -0,0 +1,24 @@
+#!/bin/sh
+# Check that the examples are position independent when built with
+# `-C relocation-model=pic`, i.e. that nothing in PrgCode needs an absolute address.
+# basic (`todo!()`) and qspi (slice indexing) are left out, they keep panic locations around.
+set -e
+
+TARGET=${TARGET:-thumbv7em-none-eabi}
+export RUSTFLAGS="-C link-arg=-Tmemory.x -C link-arg=--emit-relocs -C relocation-model=pic"
+TARGET_DIR=target/relocations
+
+failed=0
+for example in "stm32f4" "logging --features rtt-minimal,verify"; do
+    name=${example%% *}
+    # shellcheck disable=SC2086
+    cargo build --quiet --release --target "$TARGET" --target-dir "$TARGET_DIR" --example $example
+    absolute=$(readelf -rW "$TARGET_DIR/$TARGET/release/examples/$name" |
+        awk '/^Relocation section/ { code = ($3 ~ /PrgCode/) } code && /R_ARM_[A-Z_]*ABS/')
+    if [ -n "$absolute" ]; then
+        echo "$name has absolute relocations in PrgCode:"
+        echo "$absolute"
+        failed=1
+    fi
+done
+exit $failed
@@ -0,0 +1,37 @@
+#!/bin/sh
+# Compile every program in ci/compile-fail against the library and check that it fails
+# with exactly the errors listed in the matching `.stderr` file.
+#
+# A program can ask for features of the library with a `// features: a,b` line.
+#
+# Run with `BLESS=1` to write the `.stderr` files from the current output instead.
+set -e
+
+TARGET=${TARGET:-thumbv7em-none-eabi}
+
+failed=0
+for program in ci/compile-fail/*.rs; do
+    expected=${program%.rs}.stderr
+    features=$(sed -n 's|^// features: ||p' "$program")
+    target_dir=target/compile-fail/${features:-default}
+    cargo build --quiet --target "$TARGET" --target-dir "$target_dir" --lib --features "$features"
+    DEPS=$target_dir/$TARGET/debug/deps
+    RLIB=$target_dir/$TARGET/debug/libflash_algorithm.rlib
+    if rustc --edition 2021 --target "$TARGET" --crate-type bin --emit metadata \
+        -o /dev/null -L "$DEPS" --extern flash_algorithm="$RLIB" "$program" 2>"$expected.out"; then
+        echo "$program: compiled successfully"
+        failed=1
+        continue
+    fi
+    grep '^error' "$expected.out" | grep -v '^error: aborting due to' >"$expected.actual" || true
+    rm "$expected.out"
+    if [ -n "$BLESS" ]; then
+        mv "$expected.actual" "$expected"
+    elif diff -u "$expected" "$expected.actual"; then
+        rm "$expected.actual"
+    else
+        echo "$program: unexpected errors"
+        failed=1
+    fi
+done
+exit $failed
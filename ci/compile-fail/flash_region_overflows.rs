@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use flash_algorithm::{ErrorCode, FlashAlgorithm, Function};
+
+struct Algorithm;
+
+const FLASH_ADDRESS: u32 = 0xF000_0000;
+const FLASH_SIZE: u32 = 0x2000_0000;
+
+flash_algorithm::algorithm!(Algorithm, {
+    device_name: "test",
+    device_type: DeviceType::Onchip,
+    flash_address: FLASH_ADDRESS,
+    flash_size: FLASH_SIZE,
+    page_size: 0x400,
+    empty_value: 0xFF,
+    program_time_out: 1000,
+    erase_time_out: 2000,
+    sectors: [{
+        size: 0x400,
+        address: 0x0,
+    }]
+});
+
+impl FlashAlgorithm for Algorithm {
+    fn new(_address: u32, _clock: u32, _function: Function) -> Result<Self, ErrorCode> {
+        Ok(Self)
+    }
+
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, _address: u32) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn program_page(&mut self, _address: u32, _data: &[u8]) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+// features: erase-chip
+#![no_std]
+#![no_main]
+
+use flash_algorithm::{ErrorCode, FlashAlgorithm, Function};
+
+struct Algorithm;
+
+flash_algorithm::algorithm!(Algorithm, {
+    device_name: "test",
+    device_type: DeviceType::Onchip,
+    flash_address: 0x0800_0000,
+    flash_size: 0x1_0000,
+    page_size: 0x400,
+    empty_value: 0xFF,
+    program_time_out: 1000,
+    erase_time_out: 2000,
+    // Erasing 0x0 also erases 0x1000, which is preserved, in the same 8 KiB block.
+    min_erase_size: 0x2000,
+    sectors: [{
+        size: 0x1000,
+        address: 0x0,
+    }, {
+        size: 0x1000,
+        address: 0x1000,
+        preserve: true,
+    }, {
+        size: 0x1000,
+        address: 0x2000,
+    }]
+});
+
+impl FlashAlgorithm for Algorithm {
+    fn new(_address: u32, _clock: u32, _function: Function) -> Result<Self, ErrorCode> {
+        Ok(Self)
+    }
+
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, _address: u32) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn program_page(&mut self, _address: u32, _data: &[u8]) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+#!/bin/sh
+# Build the basic example with every combination of the features that change which
+# entry points the `algorithm!` macro emits.
+set -e
+
+TARGET=${TARGET:-thumbv7em-none-eabi}
+FEATURES="erase-chip verify read-flash blank-check program-verify verify-crc abort"
+
+count=$(echo $FEATURES | wc -w)
+combination=0
+while [ $combination -lt $((1 << count)) ]; do
+    enabled=panic-handler
+    bit=0
+    for feature in $FEATURES; do
+        if [ $((combination >> bit & 1)) -eq 1 ]; then
+            enabled="$enabled,$feature"
+        fi
+        bit=$((bit + 1))
+    done
+    echo "Building with $enabled"
+    cargo build --quiet --target "$TARGET" --example basic --no-default-features --features "$enabled"
+    combination=$((combination + 1))
+done
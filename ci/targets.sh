@@ -0,0 +1,23 @@
+#!/bin/sh
+# Build the basic example for a representative set of targets, to catch code that only
+# works on one architecture, like the panic handler or the inline assembly. Targets that
+# are not installed are skipped, `rustup target add` them to include them. Hard-float
+# targets are built with `fp-save`.
+set -e
+
+TARGETS=${TARGETS:-"thumbv6m-none-eabi thumbv7em-none-eabi thumbv7em-none-eabihf riscv32imac-unknown-none-elf"}
+export RUSTFLAGS="-C link-arg=-Tmemory.x"
+
+installed=$(rustup target list --installed)
+for target in $TARGETS; do
+    if ! echo "$installed" | grep -qx "$target"; then
+        echo "Skipping $target, it is not installed"
+        continue
+    fi
+    features=verify
+    case $target in
+        *eabihf) features=verify,fp-save ;;
+    esac
+    echo "Building for $target"
+    cargo build --quiet --target "$target" --target-dir target/targets --example basic --features "$features"
+done
@@ -0,0 +1,141 @@
+//! Decoding of the `FlashDevice` descriptor emitted by `algorithm!`.
+
+/// The contents of `FlashDevice`.
+pub struct FlashDevice {
+    pub version: u16,
+    pub name: String,
+    pub device_type: u16,
+    pub address: u64,
+    pub size: u64,
+    pub page_size: u32,
+    /// The field after `page_size` that CMSIS-Pack reserves.
+    pub reserved: u32,
+    pub empty_value: u8,
+    pub program_time_out: u32,
+    pub erase_time_out: u32,
+    /// `(size, address)` of each run of sectors, without the terminating entry.
+    pub sectors: Vec<(u64, u64)>,
+    /// Whether addresses are 64-bit, i.e. the algorithm was built with `addr64`.
+    pub addr64: bool,
+}
+
+/// Where the fields after `dev_type` are with 32-bit and 64-bit addresses.
+///
+/// These are the `DESCRIPTOR_LAYOUT` of `flash-algorithm` without and with `addr64`,
+/// which every algorithm checks its descriptor against when it is built.
+struct Layout {
+    addr64: bool,
+    dev_addr: usize,
+    page_size: usize,
+    empty: usize,
+    program_time_out: usize,
+    sectors: usize,
+}
+
+/// The first bytes of the descriptor that `minimal-descriptor` emits instead.
+const MINIMAL_DESCRIPTOR_MAGIC: &[u8; 4] = b"FLAM";
+
+const LAYOUTS: [Layout; 2] = [
+    Layout {
+        addr64: false,
+        dev_addr: 132,
+        page_size: 140,
+        empty: 148,
+        program_time_out: 152,
+        sectors: 160,
+    },
+    Layout {
+        addr64: true,
+        dev_addr: 136,
+        page_size: 152,
+        empty: 160,
+        program_time_out: 164,
+        sectors: 176,
+    },
+];
+
+impl FlashDevice {
+    /// Decode the bytes of the `FlashDevice` symbol.
+    ///
+    /// The address width is not stored in the descriptor. It is the one for which the
+    /// sector list ends with its terminating entry exactly at the end of the symbol.
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.starts_with(MINIMAL_DESCRIPTOR_MAGIC) {
+            return Err("FlashDevice is a minimal descriptor without the geometry \
+                 (`minimal-descriptor` feature), which is not supported"
+                .into());
+        }
+        LAYOUTS
+            .iter()
+            .find_map(|layout| Self::parse_with(data, layout))
+            .ok_or_else(|| "FlashDevice does not have the layout of a descriptor".into())
+    }
+
+    fn parse_with(data: &[u8], layout: &Layout) -> Option<Self> {
+        let word = if layout.addr64 { 8 } else { 4 };
+        let addr = |offset: usize| -> Option<u64> {
+            let bytes = data.get(offset..offset + word)?;
+            let mut buf = [0; 8];
+            buf[..word].copy_from_slice(bytes);
+            Some(u64::from_le_bytes(buf))
+        };
+        let u32_at = |offset: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(
+                data.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        };
+
+        let entries = data.get(layout.sectors..)?.chunks(2 * word);
+        let all_ones = if layout.addr64 {
+            u64::MAX
+        } else {
+            u32::MAX as u64
+        };
+        let mut sectors = Vec::new();
+        let mut terminated = false;
+        for (i, entry) in entries.enumerate() {
+            if terminated || entry.len() != 2 * word {
+                return None;
+            }
+            let offset = layout.sectors + i * 2 * word;
+            let (size, address) = (addr(offset)?, addr(offset + word)?);
+            if size == all_ones && address == all_ones {
+                terminated = true;
+            } else {
+                sectors.push((size, address));
+            }
+        }
+        if !terminated || sectors.is_empty() {
+            return None;
+        }
+
+        let name = data.get(2..130)?;
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Some(Self {
+            version: u16::from_le_bytes(data.get(0..2)?.try_into().ok()?),
+            name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+            device_type: u16::from_le_bytes(data.get(130..132)?.try_into().ok()?),
+            address: addr(layout.dev_addr)?,
+            size: addr(layout.dev_addr + word)?,
+            page_size: u32_at(layout.page_size)?,
+            reserved: u32_at(layout.page_size + 4)?,
+            empty_value: *data.get(layout.empty)?,
+            program_time_out: u32_at(layout.program_time_out)?,
+            erase_time_out: u32_at(layout.program_time_out + 4)?,
+            sectors,
+            addr64: layout.addr64,
+        })
+    }
+
+    pub fn device_type_name(&self) -> &'static str {
+        match self.device_type {
+            0 => "Unknown",
+            1 => "Onchip",
+            2 => "Ext8Bit",
+            3 => "Ext16Bit",
+            4 => "Ext32Bit",
+            5 => "ExtSpi",
+            _ => "?",
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Just enough of an ELF reader to find symbols and the bytes they point at.
+//!
+//! Only little endian files are supported, which covers every target the
+//! `flash-algorithm` crate can be built for.
+
+use std::ops::Range;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+
+pub struct Elf<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    sections: Vec<Section>,
+}
+
+pub struct Section {
+    pub name: String,
+    pub kind: u32,
+    flags: u64,
+    pub address: u64,
+    pub offset: u64,
+    pub size: u64,
+    link: u32,
+}
+
+impl Section {
+    /// Whether the section takes up memory but has no contents in the file, like `.bss`.
+    pub fn is_nobits(&self) -> bool {
+        self.kind == SHT_NOBITS
+    }
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, String> {
+        if data.get(..4) != Some(b"\x7fELF") {
+            return Err("not an ELF file".into());
+        }
+        let is_64 = match data.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err("unknown ELF class".into()),
+        };
+        if data.get(5) != Some(&1) {
+            return Err("only little endian ELF files are supported".into());
+        }
+        let mut elf = Elf {
+            data,
+            is_64,
+            sections: Vec::new(),
+        };
+
+        let (shoff, shentsize, shnum, shstrndx) = if is_64 {
+            (
+                elf.u64(0x28)?,
+                elf.u16(0x3A)?,
+                elf.u16(0x3C)?,
+                elf.u16(0x3E)?,
+            )
+        } else {
+            (
+                elf.u32(0x20)? as u64,
+                elf.u16(0x2E)?,
+                elf.u16(0x30)?,
+                elf.u16(0x32)?,
+            )
+        };
+        let mut names = Vec::new();
+        for i in 0..shnum as u64 {
+            let header = (shoff + i * shentsize as u64) as usize;
+            let name = elf.u32(header)?;
+            let kind = elf.u32(header + 4)?;
+            let section = if is_64 {
+                Section {
+                    name: String::new(),
+                    kind,
+                    flags: elf.u64(header + 0x08)?,
+                    address: elf.u64(header + 0x10)?,
+                    offset: elf.u64(header + 0x18)?,
+                    size: elf.u64(header + 0x20)?,
+                    link: elf.u32(header + 0x28)?,
+                }
+            } else {
+                Section {
+                    name: String::new(),
+                    kind,
+                    flags: elf.u32(header + 0x08)? as u64,
+                    address: elf.u32(header + 0x0C)? as u64,
+                    offset: elf.u32(header + 0x10)? as u64,
+                    size: elf.u32(header + 0x14)? as u64,
+                    link: elf.u32(header + 0x18)?,
+                }
+            };
+            names.push(name);
+            elf.sections.push(section);
+        }
+        let strings = elf.section_data(shstrndx as usize)?;
+        for (section, name) in elf.sections.iter_mut().zip(names) {
+            section.name = string_at(strings, name as usize)?;
+        }
+        Ok(elf)
+    }
+
+    /// All named symbols in the symbol table.
+    pub fn symbols(&self) -> Result<Vec<Symbol>, String> {
+        let Some(index) = self.sections.iter().position(|s| s.kind == SHT_SYMTAB) else {
+            return Err("no symbol table, was the file stripped?".into());
+        };
+        let table = self.section_data(index)?;
+        let strings = self.section_data(self.sections[index].link as usize)?;
+        let entry_size = if self.is_64 { 24 } else { 16 };
+        let mut symbols = Vec::new();
+        for entry in table.chunks_exact(entry_size) {
+            let name = u32::from_le_bytes(entry[..4].try_into().unwrap()) as usize;
+            let (address, size) = if self.is_64 {
+                (
+                    u64::from_le_bytes(entry[8..16].try_into().unwrap()),
+                    u64::from_le_bytes(entry[16..24].try_into().unwrap()),
+                )
+            } else {
+                (
+                    u32::from_le_bytes(entry[4..8].try_into().unwrap()) as u64,
+                    u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64,
+                )
+            };
+            if name != 0 {
+                symbols.push(Symbol {
+                    name: string_at(strings, name)?,
+                    address,
+                    size,
+                });
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// The file contents at `address..address + size` in the memory image.
+    pub fn read(&self, address: u64, size: u64) -> Result<&'a [u8], String> {
+        let section = self
+            .sections
+            .iter()
+            .filter(|section| section.flags & SHF_ALLOC != 0 && section.kind != SHT_NOBITS)
+            .find(|section| {
+                address >= section.address && address + size <= section.address + section.size
+            })
+            .ok_or_else(|| format!("nothing is loaded at {address:#x}"))?;
+        let start = section.offset + (address - section.address);
+        self.bytes(start as usize..(start + size) as usize)
+    }
+
+    /// All sections, in the order of the section headers.
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// The first section called `name`.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    pub fn section_data(&self, index: usize) -> Result<&'a [u8], String> {
+        let section = self
+            .sections
+            .get(index)
+            .ok_or_else(|| format!("no section {index}"))?;
+        self.bytes(section.offset as usize..(section.offset + section.size) as usize)
+    }
+
+    fn bytes(&self, range: Range<usize>) -> Result<&'a [u8], String> {
+        self.data
+            .get(range)
+            .ok_or_else(|| "the file is truncated".to_string())
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(
+            self.bytes(offset..offset + 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(
+            self.bytes(offset..offset + 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(
+            self.bytes(offset..offset + 8)?.try_into().unwrap(),
+        ))
+    }
+}
+
+fn string_at(table: &[u8], offset: usize) -> Result<String, String> {
+    let bytes = table.get(offset..).ok_or("bad string table offset")?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
@@ -0,0 +1,122 @@
+//! Conversion of an algorithm into a flat binary for loaders that do not parse ELF.
+//!
+//! The file starts with a header, all fields little endian:
+//!
+//! | Offset | Size   | Field                                                           |
+//! |--------|--------|-----------------------------------------------------------------|
+//! | 0      | 4      | `FLAB`                                                          |
+//! | 4      | 2      | The version of this layout, 4                                   |
+//! | 6      | 2      | The size of the header, 84                                      |
+//! | 8      | 4      | The load address, see below                                     |
+//! | 12     | 4      | `image_size`, the number of bytes of the image                  |
+//! | 16     | 4      | `zero_size`, the number of zeroed bytes that follow the image   |
+//! | 20     | 4      | The offset of `FlashDevice` in the file                         |
+//! | 24     | 4      | The size of `FlashDevice`                                       |
+//! | 28     | 4 × 14 | The offset of each entry point in the image                     |
+//!
+//! The image follows the header, and `FlashDevice` follows the image. A loader copies
+//! the image to RAM, clears the `zero_size` bytes after it and calls the entry points at
+//! the address it copied the image to plus their offset.
+//!
+//! - The load address is `LoadAddress` if the algorithm declared one, and the address the
+//!   image was linked for otherwise. Only a position independent algorithm can be loaded
+//!   anywhere else.
+//! - The entry points are in the order `Init`, `UnInit`, `EraseChip`, `EraseSector`,
+//!   `ProgramPage`, `Verify`, `BlankCheck`, `ReadFlash`, `ProgramAndVerify`, `Abort`,
+//!   `Capabilities`, `DumpConfig`, `VerifyInPlace`, `IsRangeBlank`; version 1 ended at
+//!   `Capabilities`, version 2 at `DumpConfig` and version 3 at `VerifyInPlace`. The offset of one the algorithm does not have is
+//!   `0xFFFF_FFFF`. On Arm the offsets of Thumb functions have the lowest bit set, like
+//!   their symbols, so a loader can branch to them with `blx`.
+//! - `FlashDevice` is only there for the loader to read, it is not part of the image.
+
+use crate::{
+    elf::{Elf, Symbol},
+    ENTRY_POINTS,
+};
+
+const MAGIC: &[u8; 4] = b"FLAB";
+const VERSION: u16 = 4;
+const HEADER_SIZE: u16 = 28 + 4 * ENTRY_POINTS.len() as u16;
+
+/// The sections a host loads to the target, see `memory.x`.
+const LOADED: [&str; 2] = ["PrgCode", "PrgData"];
+
+/// Build the flat binary of the algorithm whose symbols start with `prefix`, with the
+/// descriptor from the `descriptor` symbol.
+pub fn flat(
+    elf: &Elf,
+    symbols: &[Symbol],
+    prefix: &str,
+    descriptor: &str,
+) -> Result<Vec<u8>, String> {
+    let find = |name: &str| {
+        symbols
+            .iter()
+            .find(|symbol| symbol.name.strip_prefix(prefix) == Some(name))
+    };
+
+    let mut sections: Vec<_> = elf
+        .sections()
+        .iter()
+        .filter(|section| LOADED.contains(&section.name.as_str()) && section.size != 0)
+        .collect();
+    sections.sort_by_key(|section| section.address);
+    let start = sections
+        .first()
+        .ok_or("there is no PrgCode section")?
+        .address;
+    // Zero initialized sections at the end are cleared by the loader instead of being
+    // stored, the ones in between are filled in with zeros.
+    let mut image = Vec::new();
+    let mut end = start;
+    for section in &sections {
+        if !section.is_nobits() {
+            let offset = (section.address - start) as usize;
+            image.resize(offset, 0);
+            image.extend_from_slice(elf.read(section.address, section.size)?);
+        }
+        end = end.max(section.address + section.size);
+    }
+    let zero_size = end - start - image.len() as u64;
+
+    let device =
+        find(descriptor).ok_or_else(|| format!("there is no {prefix}{descriptor} symbol"))?;
+    let device = elf.read(device.address, device.size)?;
+    let load_address = match find("LoadAddress") {
+        Some(symbol) => u32::from_le_bytes(
+            elf.read(symbol.address, 4)?
+                .try_into()
+                .map_err(|_| "LoadAddress is not 4 bytes")?,
+        ),
+        None => u32(start, "the image address")?,
+    };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+    out.extend_from_slice(&load_address.to_le_bytes());
+    out.extend_from_slice(&u32(image.len() as u64, "the image")?.to_le_bytes());
+    out.extend_from_slice(&u32(zero_size, "the zeroed memory")?.to_le_bytes());
+    let device_offset = HEADER_SIZE as u64 + image.len() as u64;
+    out.extend_from_slice(&u32(device_offset, "the file")?.to_le_bytes());
+    out.extend_from_slice(&(device.len() as u32).to_le_bytes());
+    for name in ENTRY_POINTS {
+        let offset = match find(name) {
+            Some(symbol) if (start..end).contains(&(symbol.address & !1)) => {
+                u32(symbol.address - start, name)?
+            }
+            Some(_) => return Err(format!("{prefix}{name} is not in the image")),
+            None => u32::MAX,
+        };
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+    debug_assert_eq!(out.len(), HEADER_SIZE as usize);
+    out.extend_from_slice(&image);
+    out.extend_from_slice(device);
+    Ok(out)
+}
+
+fn u32(value: u64, what: &str) -> Result<u32, String> {
+    u32::try_from(value).map_err(|_| format!("{what} does not fit into 32 bits"))
+}
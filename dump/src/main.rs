@@ -0,0 +1,429 @@
+//! Print what a host needs to know about a flash algorithm built with `flash-algorithm`:
+//! the `FlashDevice` descriptor, the entry points that exist, the `LoadAddress`, and the
+//! enabled features and the description if they were emitted with `feature-manifest`
+//! and `description`.
+//!
+//! If the algorithm was built with `descriptor-header`, the `FlashAlgorithmHeader` at
+//! the start of `DeviceData` is checked before `FlashDevice` is decoded.
+//!
+//! ```text
+//! flash-algorithm-dump [--json] [--prefix <symbol prefix>] [--descriptor <symbol>] [--bin <output>] <algorithm ELF>
+//! ```
+//!
+//! `--prefix` selects one of several algorithms in the same ELF by the `symbol_prefix`
+//! it was declared with. `--descriptor` reads the descriptor from the symbol given as
+//! `descriptor_name` instead of `FlashDevice`, after the prefix. `--bin` additionally writes the algorithm as a flat binary with
+//! a header that locates the entry points and the descriptor, for loaders that do not
+//! parse ELF. Its layout is described in `flat.rs`.
+
+use std::{fmt::Write, process::ExitCode};
+
+use descriptor::FlashDevice;
+use elf::{Elf, Symbol};
+
+mod descriptor;
+mod elf;
+mod flat;
+
+/// The first bytes of `FlashAlgorithmHeader`.
+const DESCRIPTOR_MAGIC: &[u8; 4] = b"FLA1";
+
+/// Every entry point `algorithm!` can emit, in the order they are printed.
+const ENTRY_POINTS: [&str; 14] = [
+    "Init",
+    "UnInit",
+    "EraseChip",
+    "EraseSector",
+    "ProgramPage",
+    "Verify",
+    "BlankCheck",
+    "ReadFlash",
+    "ProgramAndVerify",
+    "Abort",
+    "Capabilities",
+    "DumpConfig",
+    "VerifyInPlace",
+    "IsRangeBlank",
+];
+
+/// Everything that is printed.
+struct Dump {
+    device: FlashDevice,
+    /// Whether there is a valid `FlashAlgorithmHeader`.
+    header: bool,
+    /// The address of each of [`ENTRY_POINTS`], if it exists.
+    entry_points: Vec<(&'static str, Option<u64>)>,
+    load_address: Option<u32>,
+    write_align: Option<u32>,
+    erase_align: Option<u32>,
+    min_program_size: Option<u32>,
+    min_erase_size: Option<u32>,
+    ram_align: Option<u32>,
+    entry_align: Option<u32>,
+    /// The erase timeout of each entry in the sector list, if the algorithm has them.
+    sector_erase_timeouts: Option<Vec<u32>>,
+    requires_erase: Option<u32>,
+    xip: Option<u32>,
+    page_buffer_count: Option<u32>,
+    /// The address of `PageBuffers`, if the algorithm provides them.
+    page_buffers: Option<u64>,
+    features: Option<Vec<String>>,
+    description: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut prefix = Some(String::new());
+    let mut descriptor = Some(String::from("FlashDevice"));
+    let mut bin = None;
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--prefix" => prefix = args.next(),
+            "--descriptor" => descriptor = args.next(),
+            "--bin" => bin = Some(args.next()),
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => path = None,
+        }
+    }
+    let (Some(path), Some(prefix), Some(descriptor), None | Some(Some(_))) =
+        (path, prefix, descriptor, &bin)
+    else {
+        eprintln!(
+            "usage: flash-algorithm-dump [--json] [--prefix <symbol prefix>] \
+             [--descriptor <symbol>] [--bin <output>] <algorithm ELF>"
+        );
+        return ExitCode::FAILURE;
+    };
+    let bin = bin.flatten();
+
+    match std::fs::read(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            if let Some(bin) = &bin {
+                let elf = Elf::parse(&data)?;
+                let flat = flat::flat(&elf, &elf.symbols()?, &prefix, &descriptor)?;
+                std::fs::write(bin, flat).map_err(|e| format!("{bin}: {e}"))?;
+            }
+            dump(&data, &prefix, &descriptor)
+        }) {
+        Ok(dump) => {
+            print!("{}", if json { dump.json() } else { dump.text() });
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn dump(data: &[u8], prefix: &str, descriptor: &str) -> Result<Dump, String> {
+    let elf = Elf::parse(data)?;
+    let symbols = elf.symbols()?;
+    let find = |name: &str| {
+        symbols
+            .iter()
+            .find(|symbol| symbol.name.strip_prefix(prefix) == Some(name))
+    };
+    let read = |symbol: &Symbol| elf.read(symbol.address, symbol.size);
+
+    let header = match find("FlashAlgorithmHeader") {
+        Some(symbol) => {
+            // With several algorithms all headers are at the start, one after another.
+            let headers = symbols
+                .iter()
+                .filter(|symbol| symbol.name.ends_with("FlashAlgorithmHeader"))
+                .count() as u64;
+            Some(check_header(&elf, read(symbol)?, symbol.address, headers)?)
+        }
+        None => None,
+    };
+    let device =
+        find(descriptor).ok_or_else(|| format!("there is no {prefix}{descriptor} symbol"))?;
+    let device = FlashDevice::parse(read(device)?)?;
+    if let Some((version, device_type)) = header {
+        if (version, device_type) != (device.version, device.device_type) {
+            return Err(format!(
+                "FlashAlgorithmHeader has version {version} and type {device_type}, \
+                 FlashDevice has {} and {}",
+                device.version, device.device_type
+            ));
+        }
+    }
+    let entry_points = ENTRY_POINTS
+        .iter()
+        // Thumb function addresses have the lowest bit set.
+        .map(|name| (*name, find(name).map(|symbol| symbol.address & !1)))
+        .collect();
+    let read_u32 = |name: &str| -> Result<Option<u32>, String> {
+        match find(name) {
+            Some(symbol) => Ok(Some(u32::from_le_bytes(
+                read(symbol)?
+                    .try_into()
+                    .map_err(|_| format!("{name} is not 4 bytes"))?,
+            ))),
+            None => Ok(None),
+        }
+    };
+    let sector_erase_timeouts = match find("SectorEraseTimeouts") {
+        Some(symbol) => {
+            let bytes = read(symbol)?;
+            if bytes.len() != 4 * device.sectors.len() {
+                return Err("SectorEraseTimeouts does not match the sectors".into());
+            }
+            Some(
+                bytes
+                    .chunks_exact(4)
+                    .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+    let features = match find("FlashAlgorithmFeatures") {
+        Some(symbol) => Some(
+            String::from_utf8_lossy(read(symbol)?)
+                .lines()
+                .map(String::from)
+                .collect(),
+        ),
+        None => None,
+    };
+    let description = match find("FlashAlgorithmDescription") {
+        Some(symbol) => {
+            let bytes = read(symbol)?;
+            let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        None => None,
+    };
+    Ok(Dump {
+        device,
+        header: header.is_some(),
+        entry_points,
+        load_address: read_u32("LoadAddress")?,
+        write_align: read_u32("WriteAlign")?,
+        erase_align: read_u32("EraseAlign")?,
+        min_program_size: read_u32("MinProgramSize")?,
+        min_erase_size: read_u32("MinEraseSize")?,
+        ram_align: read_u32("RamAlign")?,
+        entry_align: read_u32("EntryAlign")?,
+        sector_erase_timeouts,
+        requires_erase: read_u32("RequiresErase")?,
+        xip: read_u32("Xip")?,
+        page_buffer_count: read_u32("PageBufferCount")?,
+        page_buffers: find("PageBuffers").map(|symbol| symbol.address),
+        features,
+        description,
+    })
+}
+
+/// Check the magic and position of the header and return its version and device type.
+fn check_header(elf: &Elf, data: &[u8], address: u64, headers: u64) -> Result<(u16, u16), String> {
+    if data.len() != 8 || &data[..4] != DESCRIPTOR_MAGIC {
+        return Err(format!(
+            "FlashAlgorithmHeader does not start with {DESCRIPTOR_MAGIC:?}"
+        ));
+    }
+    if elf
+        .section("DeviceData")
+        .is_some_and(|section| !(section.address..section.address + 8 * headers).contains(&address))
+    {
+        return Err("FlashAlgorithmHeader is not at the start of DeviceData".into());
+    }
+    Ok((
+        u16::from_le_bytes([data[4], data[5]]),
+        u16::from_le_bytes([data[6], data[7]]),
+    ))
+}
+
+impl Dump {
+    fn text(&self) -> String {
+        let d = &self.device;
+        let mut out = String::new();
+        let _ = writeln!(out, "name              {}", d.name);
+        if let Some(description) = &self.description {
+            let _ = writeln!(out, "description       {description}");
+        }
+        let _ = writeln!(
+            out,
+            "type              {} ({})",
+            d.device_type_name(),
+            d.device_type
+        );
+        let _ = writeln!(out, "version           {}", d.version);
+        let _ = writeln!(
+            out,
+            "header            {}",
+            if self.header { "valid" } else { "-" }
+        );
+        let _ = writeln!(
+            out,
+            "flash             {:#x}..{:#x} ({:#x} bytes)",
+            d.address,
+            d.address + d.size,
+            d.size
+        );
+        let _ = writeln!(out, "page size         {:#x}", d.page_size);
+        let _ = writeln!(out, "reserved          {:#x}", d.reserved);
+        let _ = writeln!(out, "empty value       {:#04x}", d.empty_value);
+        let _ = writeln!(out, "program timeout   {} ms", d.program_time_out);
+        let _ = writeln!(out, "erase timeout     {} ms", d.erase_time_out);
+        let _ = writeln!(
+            out,
+            "addresses         {}",
+            if d.addr64 { "64-bit" } else { "32-bit" }
+        );
+        for (name, value) in [
+            ("load address", self.load_address),
+            ("write align", self.write_align),
+            ("erase align", self.erase_align),
+            ("min program size", self.min_program_size),
+            ("min erase size", self.min_erase_size),
+            ("ram align", self.ram_align),
+            ("entry align", self.entry_align),
+        ] {
+            match value {
+                Some(value) => {
+                    let _ = writeln!(out, "{name:<18}{value:#x}");
+                }
+                None => {
+                    let _ = writeln!(out, "{name:<18}-");
+                }
+            }
+        }
+        let _ = match self.requires_erase {
+            Some(0) => writeln!(out, "requires erase    no"),
+            Some(_) => writeln!(out, "requires erase    yes"),
+            None => writeln!(out, "requires erase    -"),
+        };
+        let _ = match self.xip {
+            Some(0) => writeln!(out, "xip               no"),
+            Some(_) => writeln!(out, "xip               yes"),
+            None => writeln!(out, "xip               -"),
+        };
+        let _ = match (self.page_buffer_count, self.page_buffers) {
+            (Some(count), Some(address)) => {
+                writeln!(out, "page buffers      {count} at {address:#x}")
+            }
+            (Some(count), None) => writeln!(out, "page buffers      {count}"),
+            (None, _) => writeln!(out, "page buffers      -"),
+        };
+        match &self.features {
+            Some(features) => {
+                let _ = writeln!(out, "features          {}", features.join(" "));
+            }
+            None => {
+                let _ = writeln!(out, "features          -");
+            }
+        }
+        let _ = writeln!(out, "sectors");
+        for (i, (size, address)) in d.sectors.iter().enumerate() {
+            let _ = write!(out, "  {size:#x} bytes from {:#x}", d.address + address);
+            let _ = match &self.sector_erase_timeouts {
+                Some(timeouts) => writeln!(out, ", erase timeout {} ms", timeouts[i]),
+                None => writeln!(out),
+            };
+        }
+        let _ = writeln!(out, "entry points");
+        for (name, address) in &self.entry_points {
+            match address {
+                Some(address) => {
+                    let _ = writeln!(out, "  {name:<18}{address:#x}");
+                }
+                None => {
+                    let _ = writeln!(out, "  {name:<18}-");
+                }
+            }
+        }
+        out
+    }
+
+    fn json(&self) -> String {
+        let d = &self.device;
+        let optional = |value: Option<u64>| value.map_or("null".to_string(), |v| v.to_string());
+        let sectors: Vec<_> = d
+            .sectors
+            .iter()
+            .enumerate()
+            .map(|(i, (size, address))| {
+                let timeout = self
+                    .sector_erase_timeouts
+                    .as_ref()
+                    .map(|timeouts| u64::from(timeouts[i]));
+                format!(
+                    "{{\"size\":{size},\"address\":{address},\"erase_time_out\":{}}}",
+                    optional(timeout)
+                )
+            })
+            .collect();
+        let features = self
+            .features
+            .as_ref()
+            .map_or("null".to_string(), |features| {
+                let names: Vec<_> = features.iter().map(|name| json_string(name)).collect();
+                format!("[{}]", names.join(","))
+            });
+        let entry_points: Vec<_> = self
+            .entry_points
+            .iter()
+            .map(|(name, address)| format!("\"{name}\":{}", optional(*address)))
+            .collect();
+        format!(
+            "{{\"name\":{},\"device_type\":{},\"version\":{},\"flash_address\":{},\
+             \"flash_size\":{},\"page_size\":{},\"reserved\":{},\"empty_value\":{},\"program_time_out\":{},\
+             \"erase_time_out\":{},\"addr64\":{},\"header\":{},\"load_address\":{},\"write_align\":{},\"erase_align\":{},\
+             \"min_program_size\":{},\"min_erase_size\":{},\"ram_align\":{},\"entry_align\":{},\"requires_erase\":{},\"xip\":{},\"page_buffer_count\":{},\"page_buffers\":{},\"features\":{},\"description\":{},\"sectors\":[{}],\"entry_points\":{{{}}}}}\n",
+            json_string(&d.name),
+            d.device_type,
+            d.version,
+            d.address,
+            d.size,
+            d.page_size,
+            d.reserved,
+            d.empty_value,
+            d.program_time_out,
+            d.erase_time_out,
+            d.addr64,
+            self.header,
+            optional(self.load_address.map(u64::from)),
+            optional(self.write_align.map(u64::from)),
+            optional(self.erase_align.map(u64::from)),
+            optional(self.min_program_size.map(u64::from)),
+            optional(self.min_erase_size.map(u64::from)),
+            optional(self.ram_align.map(u64::from)),
+            optional(self.entry_align.map(u64::from)),
+            self.requires_erase
+                .map_or("null".to_string(), |value| (value != 0).to_string()),
+            self.xip
+                .map_or("null".to_string(), |value| (value != 0).to_string()),
+            optional(self.page_buffer_count.map(u64::from)),
+            optional(self.page_buffers),
+            features,
+            self.description
+                .as_deref()
+                .map_or("null".to_string(), json_string),
+            sectors.join(","),
+            entry_points.join(","),
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -22,13 +22,14 @@ flash_algorithm::algorithm!(Algorithm, {
 
 impl FlashAlgorithm for Algorithm {
     fn new(
-        _address: u32,
-        _clock: u32,
+        _address: flash_algorithm::Address,
+        _clock: flash_algorithm::Clock,
         _function: flash_algorithm::Function,
     ) -> Result<Self, flash_algorithm::ErrorCode> {
         todo!()
     }
 
+    #[cfg(feature = "erase-chip")]
     fn erase_all(&mut self) -> Result<(), flash_algorithm::ErrorCode> {
         todo!()
     }
@@ -45,6 +46,7 @@ impl FlashAlgorithm for Algorithm {
         todo!()
     }
 
+    #[cfg(feature = "verify")]
     fn verify(
         &mut self,
         _address: u32,
@@ -53,4 +55,28 @@ impl FlashAlgorithm for Algorithm {
     ) -> Result<(), flash_algorithm::ErrorCode> {
         todo!()
     }
+
+    #[cfg(feature = "blank-check")]
+    fn blank_check(
+        &mut self,
+        _address: u32,
+        _size: u32,
+        _pattern: u8,
+    ) -> Result<(), flash_algorithm::ErrorCode> {
+        todo!()
+    }
+
+    #[cfg(feature = "read-flash")]
+    fn read_flash(
+        &mut self,
+        _address: u32,
+        _data: &mut [u8],
+    ) -> Result<(), flash_algorithm::ErrorCode> {
+        todo!()
+    }
+
+    #[cfg(feature = "abort")]
+    fn abort(&mut self) {
+        todo!()
+    }
 }
@@ -0,0 +1,247 @@
+//! Drive the entry points generated by `algorithm!` through Init/UnInit sequences, on the
+//! host, against a flash simulated in RAM.
+//!
+//! The instance storage of the macro is `unsafe` code: an instance must be dropped
+//! exactly once, and never before it was created. The algorithm here owns a heap buffer,
+//! so getting that wrong is undefined behaviour that Miri reports:
+//!
+//! ```text
+//! cargo +nightly miri run --example lifecycle --features std,verify
+//! ```
+//!
+//! Without Miri it still checks the return codes and the number of live instances.
+
+use std::sync::{
+    atomic::{AtomicI32, Ordering::Relaxed},
+    Mutex,
+};
+
+use flash_algorithm::{
+    ErrorCode, FlashAlgorithm, Function, FUNCTION_ERASE, FUNCTION_PROGRAM, FUNCTION_VERIFY,
+};
+
+const FLASH_ADDRESS: u32 = 0x1000_0000;
+const FLASH_SIZE: usize = 0x400;
+const SECTOR_SIZE: usize = 0x100;
+const PAGE_SIZE: usize = 0x40;
+
+/// Returned by `new` for an address outside of the flash.
+const ERR_ADDRESS: u32 = 0x100;
+/// Returned by `verify` on a mismatch.
+const ERR_MISMATCH: u32 = 0x101;
+/// Returned by `self_test` while [`BROKEN`] is set.
+#[cfg(feature = "self-test")]
+const ERR_BROKEN: u32 = 0x102;
+
+static FLASH: Mutex<[u8; FLASH_SIZE]> = Mutex::new([0; FLASH_SIZE]);
+
+/// Instances created by `new` and not dropped yet.
+static LIVE: AtomicI32 = AtomicI32::new(0);
+
+/// Makes `self_test` fail, as if the controller did not respond.
+#[cfg(feature = "self-test")]
+static BROKEN: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+struct Algorithm {
+    /// Pages are staged here, so every instance owns memory that a wrong drop would free
+    /// twice or free without it having been allocated.
+    page: Vec<u8>,
+}
+
+flash_algorithm::algorithm!(Algorithm, {
+    device_name: "lifecycle",
+    device_type: DeviceType::Onchip,
+    flash_address: FLASH_ADDRESS,
+    flash_size: FLASH_SIZE as u32,
+    page_size: PAGE_SIZE as u32,
+    empty_value: 0xFF,
+    program_time_out: 1000,
+    erase_time_out: 2000,
+    // Not a blob, leave out the section directive.
+    data_section: "",
+    sectors: [{
+        size: SECTOR_SIZE as u32,
+        address: 0x0,
+    }]
+});
+
+fn offset(address: u32, size: usize) -> Result<usize, ErrorCode> {
+    address
+        .checked_sub(FLASH_ADDRESS)
+        .map(|offset| offset as usize)
+        .filter(|offset| offset + size <= FLASH_SIZE)
+        .ok_or(ErrorCode::new(ERR_ADDRESS).unwrap())
+}
+
+impl FlashAlgorithm for Algorithm {
+    fn new(address: u32, _clock: u32, _function: Function) -> Result<Self, ErrorCode> {
+        offset(address, 0)?;
+        LIVE.fetch_add(1, Relaxed);
+        Ok(Self {
+            page: Vec::with_capacity(PAGE_SIZE),
+        })
+    }
+
+    #[cfg(feature = "self-test")]
+    fn self_test(&mut self) -> Result<(), ErrorCode> {
+        match BROKEN.load(Relaxed) {
+            true => Err(ErrorCode::new(ERR_BROKEN).unwrap()),
+            false => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "erase-chip")]
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        FLASH.lock().unwrap().fill(0xFF);
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), ErrorCode> {
+        let start = offset(address, SECTOR_SIZE)?;
+        FLASH.lock().unwrap()[start..start + SECTOR_SIZE].fill(0xFF);
+        Ok(())
+    }
+
+    fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        let start = offset(address, data.len())?;
+        self.page.clear();
+        self.page.extend_from_slice(data);
+        let mut flash = FLASH.lock().unwrap();
+        for (cell, byte) in flash[start..].iter_mut().zip(&self.page) {
+            *cell &= byte;
+        }
+        Ok(())
+    }
+
+    fn verify(&mut self, address: u32, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode> {
+        let start = offset(address, size as usize)?;
+        let flash = FLASH.lock().unwrap();
+        let flash = &flash[start..start + size as usize];
+        let matches = match data {
+            Some(data) => flash == &data[..size as usize],
+            None => flash.iter().all(|byte| *byte == 0xFF),
+        };
+        match matches {
+            true => Ok(()),
+            false => Err(ErrorCode::new(ERR_MISMATCH).unwrap()),
+        }
+    }
+
+    #[cfg(feature = "blank-query")]
+    fn is_range_blank(&mut self, address: u32, size: u32, erased: u8) -> Result<bool, ErrorCode> {
+        let start = offset(address, size as usize)?;
+        let flash = FLASH.lock().unwrap();
+        Ok(flash[start..start + size as usize]
+            .iter()
+            .all(|byte| *byte == erased))
+    }
+}
+
+impl Drop for Algorithm {
+    fn drop(&mut self) {
+        LIVE.fetch_sub(1, Relaxed);
+    }
+}
+
+fn live() -> i32 {
+    LIVE.load(Relaxed)
+}
+
+fn main() {
+    let data = [0x5A; PAGE_SIZE];
+    unsafe {
+        // Nothing to drop before the first `Init`.
+        assert_eq!(UnInit(), 1);
+        assert_eq!(EraseSector(FLASH_ADDRESS), 1);
+
+        // A regular session.
+        assert_eq!(Init(FLASH_ADDRESS, 0, FUNCTION_ERASE), 0);
+        assert_eq!(live(), 1);
+        assert_eq!(EraseSector(FLASH_ADDRESS), 0);
+        assert_eq!(UnInit(), 0);
+        assert_eq!(live(), 0);
+        assert_eq!(UnInit(), 1);
+
+        // `Init` without `UnInit` replaces the instance.
+        assert_eq!(Init(FLASH_ADDRESS, 0, FUNCTION_PROGRAM), 0);
+        assert_eq!(Init(FLASH_ADDRESS, 0, FUNCTION_PROGRAM), 0);
+        assert_eq!(live(), 1);
+        assert_eq!(
+            ProgramPage(FLASH_ADDRESS, PAGE_SIZE as u32, data.as_ptr()),
+            0
+        );
+
+        // An unknown function leaves the instance alone.
+        assert_eq!(Init(FLASH_ADDRESS, 0, 0x55), 1);
+        assert_eq!(live(), 1);
+
+        // A failing `new` drops the old instance and leaves none behind.
+        assert_eq!(Init(0, 0, FUNCTION_VERIFY), ERR_ADDRESS);
+        assert_eq!(live(), 0);
+        assert_eq!(Verify(FLASH_ADDRESS, PAGE_SIZE as u32, data.as_ptr()), 1);
+        assert_eq!(UnInit(), 1);
+
+        // So does a failing self-test, after the new instance was created.
+        #[cfg(feature = "self-test")]
+        {
+            assert_eq!(Init(FLASH_ADDRESS, 0, FUNCTION_VERIFY), 0);
+            BROKEN.store(true, Relaxed);
+            assert_eq!(Init(FLASH_ADDRESS, 0, FUNCTION_VERIFY), ERR_BROKEN);
+            assert_eq!(live(), 0);
+            assert_eq!(UnInit(), 1);
+            BROKEN.store(false, Relaxed);
+        }
+
+        assert_eq!(Init(FLASH_ADDRESS, 0, FUNCTION_VERIFY), 0);
+        assert_eq!(Verify(FLASH_ADDRESS, PAGE_SIZE as u32, data.as_ptr()), 0);
+        // Without data the region has to be erased, which only the next page is.
+        let next_page = FLASH_ADDRESS + PAGE_SIZE as u32;
+        assert_eq!(Verify(next_page, PAGE_SIZE as u32, core::ptr::null()), 0);
+        assert_eq!(
+            Verify(FLASH_ADDRESS, 2 * PAGE_SIZE as u32, core::ptr::null()),
+            ERR_MISMATCH
+        );
+        // The same comparison from a copy in RAM, which has to be somewhere.
+        #[cfg(feature = "verify-in-place")]
+        {
+            assert_eq!(
+                VerifyInPlace(FLASH_ADDRESS, data.as_ptr(), PAGE_SIZE as u32),
+                0
+            );
+            assert_eq!(
+                VerifyInPlace(next_page, data.as_ptr(), PAGE_SIZE as u32),
+                ERR_MISMATCH
+            );
+            assert_eq!(
+                VerifyInPlace(FLASH_ADDRESS, core::ptr::null(), PAGE_SIZE as u32),
+                flash_algorithm::ERR_OUT_OF_BOUNDS.get()
+            );
+        }
+        // Whether a region is erased is an answer, not an error.
+        #[cfg(feature = "blank-query")]
+        {
+            let mut blank = 2;
+            assert_eq!(IsRangeBlank(next_page, PAGE_SIZE as u32, &mut blank), 0);
+            assert_eq!(blank, 1);
+            assert_eq!(
+                IsRangeBlank(FLASH_ADDRESS, 2 * PAGE_SIZE as u32, &mut blank),
+                0
+            );
+            assert_eq!(blank, 0);
+            assert_eq!(
+                IsRangeBlank(FLASH_ADDRESS, 1, core::ptr::null_mut()),
+                flash_algorithm::ERR_OUT_OF_BOUNDS.get()
+            );
+        }
+        assert_eq!(UnInit(), 0);
+    }
+    assert_eq!(live(), 0);
+    // Only the calls that succeeded are counted.
+    #[cfg(feature = "counters")]
+    {
+        let counters = &flash_algorithm::COUNTERS;
+        assert_eq!(counters.sectors_erased.load(Relaxed), 1);
+        assert_eq!(counters.pages_programmed.load(Relaxed), 1);
+    }
+    println!("lifecycle ok");
+}
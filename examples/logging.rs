@@ -0,0 +1,141 @@
+//! Log every operation over RTT, for debugging an algorithm while the host runs it.
+//!
+//! The algorithm programs a 16 KiB region of RAM (SRAM2 of an STM32F4) as if it was
+//! flash, so the log is the interesting part: `erase_sector`, `program_page` and
+//! `verify` write a line with their address and size to RTT up channel 0 with
+//! [`log_bytes()`], and a failed verification dumps the flash contents with
+//! [`hexdump()`]. Attach any RTT viewer while the host flashes, for example
+//! `probe-rs attach --rtt-scan-memory`, to see lines like
+//!
+//! ```text
+//! init 0x2001c000 0x2
+//! erase 0x2001c000 0x1000
+//! program 0x2001c000 0x100
+//! ```
+//!
+//! Nothing is formatted with `core::fmt`, which would pull in data that contains
+//! pointers and break position independence.
+//!
+//! This example needs the `rtt-minimal` and `verify` features.
+
+#![no_std]
+#![no_main]
+
+use core::ptr::{read_volatile, write_volatile};
+
+use flash_algorithm::{hexdump, log_bytes, ErrorCode, FlashAlgorithm, Function};
+
+const FLASH_BASE: u32 = 0x2001_C000;
+const FLASH_SIZE: u32 = 0x4000;
+const SECTOR_SIZE: u32 = 0x1000;
+const PAGE_SIZE: u32 = 0x100;
+const ERASED: u8 = 0xFF;
+
+/// Returned by `verify` on a mismatch.
+const ERR_MISMATCH: u32 = 0x100;
+
+struct Algorithm;
+
+flash_algorithm::algorithm!(Algorithm, {
+    device_name: "ram-logging",
+    device_type: DeviceType::Onchip,
+    flash_address: FLASH_BASE,
+    flash_size: FLASH_SIZE,
+    page_size: PAGE_SIZE,
+    empty_value: ERASED,
+    program_time_out: 10,
+    erase_time_out: 10,
+    // RAM is simply overwritten.
+    requires_erase: false,
+    sectors: [{
+        size: SECTOR_SIZE,
+        address: 0x0,
+    }]
+});
+
+/// Log `operation`, `address` and `size` as one line of text.
+fn log(operation: &[u8], address: u32, size: u32) {
+    // The longest operation name, two `0x` numbers, the separators and the newline.
+    let mut line = [0; 40];
+    let mut len = 0;
+    for part in [operation, b" "] {
+        line[len..len + part.len()].copy_from_slice(part);
+        len += part.len();
+    }
+    len += hex(address, &mut line[len..]);
+    line[len] = b' ';
+    len += 1;
+    len += hex(size, &mut line[len..]);
+    line[len] = b'\n';
+    log_bytes(&line[..len + 1]);
+}
+
+/// Write `value` as `0x` and lowercase hex digits without leading zeros to `out`,
+/// returning the number of bytes written.
+fn hex(value: u32, out: &mut [u8]) -> usize {
+    out[..2].copy_from_slice(b"0x");
+    let digits = (8 - value.leading_zeros() as usize / 4).max(1);
+    for i in 0..digits {
+        let nibble = (value >> (4 * (digits - 1 - i))) & 0xF;
+        out[2 + i] = match nibble {
+            0..=9 => b'0' + nibble as u8,
+            _ => b'a' + nibble as u8 - 10,
+        };
+    }
+    2 + digits
+}
+
+impl FlashAlgorithm for Algorithm {
+    fn new(address: u32, _clock: u32, function: Function) -> Result<Self, ErrorCode> {
+        log(b"init", address, function as u32);
+        Ok(Self)
+    }
+
+    #[cfg(feature = "erase-chip")]
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        log(b"erase-chip", FLASH_BASE, FLASH_SIZE);
+        fill(FLASH_BASE, FLASH_SIZE);
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), ErrorCode> {
+        log(b"erase", address, SECTOR_SIZE);
+        fill(address, SECTOR_SIZE);
+        Ok(())
+    }
+
+    fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        log(b"program", address, data.len() as u32);
+        for (i, byte) in data.iter().enumerate() {
+            unsafe { write_volatile((address as usize + i) as *mut u8, *byte) };
+        }
+        Ok(())
+    }
+
+    fn verify(&mut self, address: u32, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode> {
+        log(b"verify", address, size);
+        let matches = (0..size as usize).all(|i| {
+            let byte = unsafe { read_volatile((address as usize + i) as *const u8) };
+            byte == data.map_or(ERASED, |data| data[i])
+        });
+        match matches {
+            true => Ok(()),
+            false => {
+                log(b"mismatch", address, size);
+                // Show the start of what is actually there.
+                let actual = unsafe {
+                    core::slice::from_raw_parts(address as *const u8, size.min(64) as usize)
+                };
+                hexdump(address, actual);
+                Err(ErrorCode::new(ERR_MISMATCH).unwrap())
+            }
+        }
+    }
+}
+
+/// Set `size` bytes from `address` to the erased value.
+fn fill(address: u32, size: u32) {
+    for i in 0..size as usize {
+        unsafe { write_volatile((address as usize + i) as *mut u8, ERASED) };
+    }
+}
@@ -0,0 +1,307 @@
+//! Flash algorithm for a 16 MiB QSPI NOR flash behind the QUADSPI controller of an STM32H7.
+//!
+//! External flash needs a bit more setup than internal flash: the controller is
+//! configured in `new`, every flash command is sent through it and reads go
+//! through the memory mapped window. Verification reads the flash back into a
+//! page buffer that lives in its own section so it does not take up stack space.
+//!
+//! This example needs the `read-flash` and `verify` features.
+
+#![no_std]
+#![no_main]
+
+use core::ptr::{read_volatile, write_volatile};
+
+use flash_algorithm::{mmio, pages, poll_until, ErrorCode, FlashAlgorithm, Function, ERR_ADDRESS};
+
+/// Where the controller maps the flash in memory mapped mode.
+const FLASH_BASE: u32 = 0x9000_0000;
+const FLASH_SIZE: u32 = 0x100_0000;
+const PAGE_SIZE: usize = 0x100;
+const SECTOR_SIZE: u32 = 0x1000;
+const ERASED: u8 = 0xFF;
+/// Worst case page program time in milliseconds.
+const PROGRAM_TIME_OUT: u32 = 100;
+/// Worst case sector erase time in milliseconds.
+const ERASE_TIME_OUT: u32 = 400;
+/// How many times a status register is polled before giving up.
+const MAX_POLLS: u32 = 1_000_000;
+
+const QUADSPI: usize = Algorithm::CONTROLLER_BASE.unwrap();
+const QUADSPI_CR: usize = QUADSPI;
+const QUADSPI_DCR: usize = QUADSPI + 0x04;
+const QUADSPI_SR: usize = QUADSPI + 0x08;
+const QUADSPI_FCR: usize = QUADSPI + 0x0C;
+const QUADSPI_DLR: usize = QUADSPI + 0x10;
+const QUADSPI_CCR: usize = QUADSPI + 0x14;
+const QUADSPI_AR: usize = QUADSPI + 0x18;
+/// Accessed a byte at a time, so the FIFO moves one byte per access.
+const QUADSPI_DR: *mut u8 = (QUADSPI + 0x20) as *mut u8;
+
+const CR_EN: u32 = 1 << 0;
+const CR_ABORT: u32 = 1 << 1;
+/// AHB clock / 4.
+const CR_PRESCALER: u32 = 3 << 24;
+/// 2^(23 + 1) bytes of flash.
+const DCR_FSIZE: u32 = 23 << 16;
+const SR_TCF: u32 = 1 << 1;
+const SR_FTF: u32 = 1 << 2;
+const SR_BUSY: u32 = 1 << 5;
+const FCR_CTCF: u32 = 1 << 1;
+
+const CCR_IMODE_SINGLE: u32 = 1 << 8;
+const CCR_ADMODE_SINGLE: u32 = 1 << 10;
+const CCR_ADSIZE_24: u32 = 2 << 12;
+const CCR_DMODE_SINGLE: u32 = 1 << 24;
+const CCR_FMODE_READ: u32 = 1 << 26;
+const CCR_FMODE_MEMORY_MAPPED: u32 = 3 << 26;
+
+const CMD_WRITE_ENABLE: u32 = 0x06;
+const CMD_READ_STATUS: u32 = 0x05;
+const CMD_PAGE_PROGRAM: u32 = 0x02;
+const CMD_SECTOR_ERASE: u32 = 0x20;
+const CMD_CHIP_ERASE: u32 = 0xC7;
+const CMD_READ: u32 = 0x03;
+const CMD_READ_ID: u32 = 0x9F;
+
+const STATUS_WIP: u8 = 1 << 0;
+
+/// Returned when the flash does not answer to the identification command.
+const ERR_NO_FLASH: u32 = 0x100;
+/// Returned by `verify` when the contents differ from the expected data.
+const ERR_MISMATCH: u32 = 0x101;
+
+/// Scratch space to read the flash back into, one page at a time.
+#[link_section = ".bss.page_buffer"]
+static mut PAGE_BUFFER: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+struct Algorithm {
+    /// Whether the controller is currently in memory mapped mode.
+    memory_mapped: bool,
+}
+
+flash_algorithm::algorithm!(Algorithm, {
+    device_name: "qspi nor 16MiB",
+    device_type: DeviceType::ExtSpi,
+    flash_address: FLASH_BASE,
+    flash_size: FLASH_SIZE,
+    page_size: PAGE_SIZE as u32,
+    empty_value: ERASED,
+    program_time_out: PROGRAM_TIME_OUT,
+    erase_time_out: ERASE_TIME_OUT,
+    controller_base: 0x5200_5000,
+    // For loaders that map the code at a 256 byte boundary.
+    entry_align: 256,
+    sectors: [{
+        size: SECTOR_SIZE,
+        address: 0x0,
+    }]
+});
+
+impl Algorithm {
+    fn wait(&self, mask: u32, value: u32) -> Result<(), ErrorCode> {
+        poll_until(
+            || unsafe { mmio::read32(QUADSPI_SR) } & mask == value,
+            MAX_POLLS,
+        )
+    }
+
+    /// Leave memory mapped mode, which has to be aborted before indirect commands work.
+    fn indirect_mode(&mut self) -> Result<(), ErrorCode> {
+        if self.memory_mapped {
+            unsafe { mmio::write32(QUADSPI_CR, CR_PRESCALER | CR_EN | CR_ABORT) };
+            self.wait(SR_BUSY, 0)?;
+            self.memory_mapped = false;
+        }
+        Ok(())
+    }
+
+    fn memory_mapped_mode(&mut self) -> Result<(), ErrorCode> {
+        if !self.memory_mapped {
+            self.wait(SR_BUSY, 0)?;
+            unsafe {
+                mmio::write32(
+                    QUADSPI_CCR,
+                    CCR_FMODE_MEMORY_MAPPED
+                        | CCR_DMODE_SINGLE
+                        | CCR_ADSIZE_24
+                        | CCR_ADMODE_SINGLE
+                        | CCR_IMODE_SINGLE
+                        | CMD_READ,
+                )
+            };
+            self.memory_mapped = true;
+        }
+        Ok(())
+    }
+
+    /// Send a command, optionally with an address, and write `data` after it.
+    fn command(&mut self, ccr: u32, address: Option<u32>, data: &[u8]) -> Result<(), ErrorCode> {
+        self.indirect_mode()?;
+        self.wait(SR_BUSY, 0)?;
+        unsafe {
+            if !data.is_empty() {
+                mmio::write32(QUADSPI_DLR, data.len() as u32 - 1);
+            }
+            mmio::write32(QUADSPI_CCR, ccr);
+            if let Some(address) = address {
+                mmio::write32(QUADSPI_AR, address);
+            }
+        }
+        for byte in data {
+            self.wait(SR_FTF, SR_FTF)?;
+            unsafe { write_volatile(QUADSPI_DR, *byte) };
+        }
+        self.wait(SR_TCF, SR_TCF)?;
+        unsafe { mmio::write32(QUADSPI_FCR, FCR_CTCF) };
+        Ok(())
+    }
+
+    /// Send a command and read `data.len()` bytes of response.
+    fn query(&mut self, instruction: u32, data: &mut [u8]) -> Result<(), ErrorCode> {
+        self.indirect_mode()?;
+        self.wait(SR_BUSY, 0)?;
+        unsafe {
+            mmio::write32(QUADSPI_DLR, data.len() as u32 - 1);
+            mmio::write32(
+                QUADSPI_CCR,
+                CCR_FMODE_READ | CCR_DMODE_SINGLE | CCR_IMODE_SINGLE | instruction,
+            );
+        }
+        for byte in data {
+            self.wait(SR_FTF, SR_FTF)?;
+            *byte = unsafe { read_volatile(QUADSPI_DR) };
+        }
+        self.wait(SR_TCF, SR_TCF)?;
+        unsafe { mmio::write32(QUADSPI_FCR, FCR_CTCF) };
+        Ok(())
+    }
+
+    fn write_enable(&mut self) -> Result<(), ErrorCode> {
+        self.command(CCR_IMODE_SINGLE | CMD_WRITE_ENABLE, None, &[])
+    }
+
+    /// Poll the status register of the flash until the write in progress bit clears.
+    fn wait_idle(&mut self) -> Result<(), ErrorCode> {
+        let mut result = Ok(());
+        poll_until(
+            || {
+                let mut status = [0];
+                // Stop polling on an error, it is returned below.
+                match self.query(CMD_READ_STATUS, &mut status) {
+                    Ok(()) => status[0] & STATUS_WIP == 0,
+                    Err(e) => {
+                        result = Err(e);
+                        true
+                    }
+                }
+            },
+            MAX_POLLS,
+        )?;
+        result
+    }
+
+    /// Read the flash through the memory mapped window.
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<(), ErrorCode> {
+        address
+            .checked_sub(FLASH_BASE)
+            .filter(|offset| *offset <= FLASH_SIZE && data.len() as u32 <= FLASH_SIZE - offset)
+            .ok_or(ERR_ADDRESS)?;
+        self.memory_mapped_mode()?;
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = unsafe { read_volatile((address + i as u32) as *const u8) };
+        }
+        Ok(())
+    }
+
+    /// Turn an absolute address into an offset within the flash chip.
+    fn offset(address: u32) -> u32 {
+        address - FLASH_BASE
+    }
+}
+
+impl FlashAlgorithm for Algorithm {
+    fn new(_address: u32, _clock: u32, _function: Function) -> Result<Self, ErrorCode> {
+        unsafe {
+            mmio::write32(QUADSPI_CR, 0);
+            mmio::write32(QUADSPI_DCR, DCR_FSIZE);
+            mmio::write32(QUADSPI_CR, CR_PRESCALER | CR_EN);
+        }
+        let mut this = Self {
+            memory_mapped: false,
+        };
+
+        // Make sure there is a flash chip answering before doing anything else.
+        let mut id = [0; 3];
+        this.query(CMD_READ_ID, &mut id)?;
+        if id == [0; 3] || id == [0xFF; 3] {
+            return Err(ErrorCode::new(ERR_NO_FLASH).unwrap());
+        }
+        Ok(this)
+    }
+
+    #[cfg(feature = "erase-chip")]
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        self.write_enable()?;
+        self.command(CCR_IMODE_SINGLE | CMD_CHIP_ERASE, None, &[])?;
+        self.wait_idle()
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), ErrorCode> {
+        self.write_enable()?;
+        self.command(
+            CCR_ADSIZE_24 | CCR_ADMODE_SINGLE | CCR_IMODE_SINGLE | CMD_SECTOR_ERASE,
+            Some(Self::offset(address)),
+            &[],
+        )?;
+        self.wait_idle()
+    }
+
+    fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        // The flash wraps around within a page, so never cross a page boundary in one command.
+        for (address, part) in pages(address, data, PAGE_SIZE as u32) {
+            self.write_enable()?;
+            self.command(
+                CCR_DMODE_SINGLE
+                    | CCR_ADSIZE_24
+                    | CCR_ADMODE_SINGLE
+                    | CCR_IMODE_SINGLE
+                    | CMD_PAGE_PROGRAM,
+                Some(Self::offset(address)),
+                part,
+            )?;
+            self.wait_idle()?;
+        }
+        Ok(())
+    }
+
+    fn verify(&mut self, address: u32, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode> {
+        let buffer = unsafe { &mut *core::ptr::addr_of_mut!(PAGE_BUFFER) };
+        let mut done = 0;
+        while done < size {
+            let len = (size - done).min(PAGE_SIZE as u32);
+            let chunk = &mut buffer[..len as usize];
+            self.read(address.checked_add(done).ok_or(ERR_ADDRESS)?, chunk)?;
+            let matches = match data {
+                Some(data) => chunk == &data[done as usize..][..len as usize],
+                None => chunk.iter().all(|byte| *byte == ERASED),
+            };
+            if !matches {
+                return Err(ErrorCode::new(ERR_MISMATCH).unwrap());
+            }
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn read_flash(&mut self, address: u32, data: &mut [u8]) -> Result<(), ErrorCode> {
+        self.read(address, data)
+    }
+}
+
+impl Drop for Algorithm {
+    fn drop(&mut self) {
+        // Leave the controller memory mapped so the application can execute from it.
+        let _ = self.memory_mapped_mode();
+    }
+}
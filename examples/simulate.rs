@@ -0,0 +1,208 @@
+//! Flash an image through the entry points generated by `algorithm!`, on the host, the
+//! way probe-rs drives an algorithm on the target: erase every sector the image
+//! touches, program it page by page and verify it, each step in its own
+//! `Init`/`UnInit` session.
+//!
+//! The algorithm writes to a NOR flash simulated in RAM. Everything goes through
+//! [`Host`], so the status codes are the ones a real host would see:
+//!
+//! ```text
+//! cargo run --example simulate --features std,verify
+//! ```
+//!
+//! With `verify-progress` it also checks the addresses `Verify` returns.
+
+use std::sync::Mutex;
+
+use flash_algorithm::{host::Host, ErrorCode, FlashAlgorithm, Function};
+
+const FLASH_ADDRESS: u32 = 0x1000_0000;
+const FLASH_SIZE: usize = 0x1000;
+const SECTOR_SIZE: usize = 0x400;
+const PAGE_SIZE: usize = 0x100;
+const ERASED: u8 = 0xFF;
+
+/// Returned for an address outside of the simulated flash.
+const ERR_ADDRESS: u32 = 0x100;
+/// Returned when programming would have to turn a 0 bit back into a 1.
+const ERR_NOT_ERASED: u32 = 0x101;
+/// Returned by `verify` on a mismatch.
+const ERR_MISMATCH: u32 = 0x102;
+
+/// The simulated flash, starting out with random looking contents like a real one.
+static FLASH: Mutex<[u8; FLASH_SIZE]> = Mutex::new([0x5A; FLASH_SIZE]);
+
+struct Algorithm;
+
+flash_algorithm::algorithm!(Algorithm, {
+    device_name: "simulate",
+    device_type: DeviceType::Onchip,
+    flash_address: FLASH_ADDRESS,
+    flash_size: FLASH_SIZE as u32,
+    page_size: PAGE_SIZE as u32,
+    empty_value: ERASED,
+    program_time_out: 1000,
+    erase_time_out: 2000,
+    // Not a blob, leave out the section directive.
+    data_section: "",
+    sectors: [{
+        size: SECTOR_SIZE as u32,
+        address: 0x0,
+    }]
+});
+
+fn range(address: u32, size: usize) -> Result<std::ops::Range<usize>, ErrorCode> {
+    let start = address
+        .checked_sub(FLASH_ADDRESS)
+        .map(|offset| offset as usize)
+        .filter(|offset| offset + size <= FLASH_SIZE)
+        .ok_or(ErrorCode::new(ERR_ADDRESS).unwrap())?;
+    Ok(start..start + size)
+}
+
+impl FlashAlgorithm for Algorithm {
+    fn new(_address: u32, _clock: u32, _function: Function) -> Result<Self, ErrorCode> {
+        Ok(Self)
+    }
+
+    #[cfg(feature = "erase-chip")]
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        FLASH.lock().unwrap().fill(ERASED);
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), ErrorCode> {
+        let range = range(address, SECTOR_SIZE)?;
+        FLASH.lock().unwrap()[range].fill(ERASED);
+        Ok(())
+    }
+
+    fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        let range = range(address, data.len())?;
+        let mut flash = FLASH.lock().unwrap();
+        for (cell, byte) in flash[range].iter_mut().zip(data) {
+            if *cell & byte != *byte {
+                return Err(ErrorCode::new(ERR_NOT_ERASED).unwrap());
+            }
+            *cell &= byte;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "verify-progress"))]
+    fn verify(&mut self, address: u32, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode> {
+        match first_mismatch(address, size, data)? {
+            None => Ok(()),
+            Some(_) => Err(ErrorCode::new(ERR_MISMATCH).unwrap()),
+        }
+    }
+
+    #[cfg(feature = "verify-progress")]
+    fn verify(
+        &mut self,
+        address: u32,
+        size: u32,
+        data: Option<&[u8]>,
+    ) -> Result<u32, (u32, ErrorCode)> {
+        match first_mismatch(address, size, data).map_err(|e| (0, e))? {
+            None => Ok(size),
+            Some(offset) => Err((offset, ErrorCode::new(ERR_MISMATCH).unwrap())),
+        }
+    }
+}
+
+/// The offset of the first byte that differs from `data`, or is not erased for `None`.
+fn first_mismatch(address: u32, size: u32, data: Option<&[u8]>) -> Result<Option<u32>, ErrorCode> {
+    let range = range(address, size as usize)?;
+    let flash = FLASH.lock().unwrap();
+    let offset = match data {
+        Some(data) => flash[range].iter().zip(data).position(|(a, b)| a != b),
+        None => flash[range].iter().position(|byte| *byte != ERASED),
+    };
+    Ok(offset.map(|offset| offset as u32))
+}
+
+/// Flash `image` at `address` in three sessions, like a host does.
+fn flash(host: &mut Host, address: u32, image: &[u8]) -> Result<(), ErrorCode> {
+    host.init(address, 0, Function::Erase)?;
+    let first = address - (address - FLASH_ADDRESS) % SECTOR_SIZE as u32;
+    for sector in (first..address + image.len() as u32).step_by(SECTOR_SIZE) {
+        host.erase_sector(sector)?;
+    }
+    host.uninit()?;
+
+    host.init(address, 0, Function::Program)?;
+    for (i, page) in image.chunks(PAGE_SIZE).enumerate() {
+        host.program_page(address + (i * PAGE_SIZE) as u32, page)?;
+    }
+    host.uninit()?;
+
+    host.init(address, 0, Function::Verify)?;
+    host.verify(address, image.len() as u32, Some(image))?;
+    host.uninit()
+}
+
+fn main() {
+    let mut host = unsafe { Host::new(flash_algorithm::entry_points!()) };
+    let image: Vec<u8> = (0..0x680).map(|i| (i * 7) as u8).collect();
+    let address = FLASH_ADDRESS + 0x200;
+
+    flash(&mut host, address, &image).unwrap();
+    let flash_contents = *FLASH.lock().unwrap();
+    assert_eq!(&flash_contents[0x200..0x880], &image[..]);
+    // Erased up to the end of the last sector, untouched before the first one.
+    assert!(flash_contents[0x880..0xC00]
+        .iter()
+        .all(|byte| *byte == ERASED));
+    assert!(flash_contents[..0x200].iter().all(|byte| *byte == ERASED));
+    assert!(flash_contents[0xC00..].iter().all(|byte| *byte == 0x5A));
+
+    // Errors come back as the codes the algorithm returned.
+    let code = |result: Result<(), ErrorCode>| result.unwrap_err().get();
+    host.init(FLASH_ADDRESS, 0, Function::Erase).unwrap();
+    // With `strict-geometry` the shim rejects these before the algorithm sees them.
+    #[cfg(feature = "strict-geometry")]
+    let (outside, past_end) = (
+        flash_algorithm::ERR_ADDRESS.get(),
+        flash_algorithm::ERR_OUT_OF_BOUNDS.get(),
+    );
+    #[cfg(not(feature = "strict-geometry"))]
+    let (outside, past_end) = (ERR_ADDRESS, ERR_ADDRESS);
+    let end = FLASH_ADDRESS + FLASH_SIZE as u32;
+    assert_eq!(code(host.erase_sector(end)), outside);
+    host.uninit().unwrap();
+    host.init(FLASH_ADDRESS, 0, Function::Program).unwrap();
+    assert_eq!(code(host.program_page(address, &[0xFF])), ERR_NOT_ERASED);
+    assert_eq!(code(host.program_page(0, &[0])), past_end);
+    assert_eq!(code(host.program_page(end - 0x10, &[0; 0x11])), past_end);
+    assert_eq!(host.program_page(end - 0x10, &[0; 0x10]), Ok(()));
+    host.uninit().unwrap();
+    host.init(FLASH_ADDRESS, 0, Function::Verify).unwrap();
+    assert_eq!(code(host.verify(address, 1, Some(&[0xAA]))), ERR_MISMATCH);
+    assert_eq!(code(host.verify(address, 0x10, None)), ERR_MISMATCH);
+    assert_eq!(host.verify(address + 0x680, 0x10, None), Ok(()));
+    // With `verify-progress` `Verify` also says where verification stopped.
+    #[cfg(feature = "verify-progress")]
+    {
+        let mut changed = image[..0x20].to_vec();
+        changed[0x13] ^= 1;
+        assert_eq!(
+            host.verify_progress(address, 0x20, Some(&changed)),
+            (address + 0x13, ERR_MISMATCH)
+        );
+        assert_eq!(
+            host.verify_progress(address, 0x20, Some(&image[..0x20])),
+            (address + 0x20, 0)
+        );
+        assert_eq!(
+            host.verify_progress(address, 0x10, None),
+            (address, ERR_MISMATCH)
+        );
+    }
+    host.uninit().unwrap();
+
+    // Outside of a session the entry points refuse to run.
+    assert_eq!(code(host.uninit()), 1);
+    assert_eq!(code(host.erase_sector(FLASH_ADDRESS)), 1);
+    println!("simulate ok");
+}
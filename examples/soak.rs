@@ -0,0 +1,215 @@
+//! Run erase, program and verify cycles against a flash simulated in RAM, on the host.
+//!
+//! This checks the contract between the host and [`FlashAlgorithm`] without any
+//! hardware: every cycle creates the algorithm for one function, the way `Init` does,
+//! and the flash has to end up holding exactly the programmed image. It needs the
+//! `std` and `verify` features:
+//!
+//! ```text
+//! cargo run --example soak --features std,verify
+//! ```
+
+use flash_algorithm::{pages, verify_sparse, Addr, ErrorCode, FlashAlgorithm, Function, Words};
+
+const FLASH_ADDRESS: Addr = 0x1000_0000;
+const FLASH_SIZE: usize = 0x1_0000;
+const SECTOR_SIZE: usize = 0x1000;
+const PAGE_SIZE: usize = 0x100;
+/// The size of the buffers the host sends to `program_page`.
+const CHUNK_SIZE: usize = PAGE_SIZE * 3 / 2;
+const ERASED: u8 = 0xFF;
+const CYCLES: u32 = 16;
+
+/// Returned for an address outside of the simulated flash.
+const ERR_ADDRESS: u32 = 0x100;
+/// Returned when programming would have to turn a 0 bit back into a 1.
+const ERR_NOT_ERASED: u32 = 0x101;
+/// Returned by `verify` on a mismatch.
+const ERR_MISMATCH: u32 = 0x102;
+
+/// NOR flash in RAM: erasing sets all bits and programming can only clear them.
+struct Algorithm {
+    flash: Vec<u8>,
+}
+
+impl Algorithm {
+    fn range(address: Addr, size: usize) -> Result<std::ops::Range<usize>, ErrorCode> {
+        let start = address
+            .checked_sub(FLASH_ADDRESS)
+            .map(|offset| offset as usize)
+            .filter(|offset| offset + size <= FLASH_SIZE)
+            .ok_or(ErrorCode::new(ERR_ADDRESS).unwrap())?;
+        Ok(start..start + size)
+    }
+
+    /// The blank check a controller would do in hardware.
+    fn blank(flash: &[u8], address: Addr, size: u32) -> Result<(), ErrorCode> {
+        let range = Self::range(address, size as usize)?;
+        match flash[range].iter().all(|byte| *byte == ERASED) {
+            true => Ok(()),
+            false => Err(ErrorCode::new(ERR_MISMATCH).unwrap()),
+        }
+    }
+
+    /// Program the part of a page at `address`.
+    fn program(&mut self, address: Addr, data: &[u8]) -> Result<(), ErrorCode> {
+        let range = Self::range(address, data.len())?;
+        assert_eq!(
+            range.start / PAGE_SIZE,
+            (range.end - 1) / PAGE_SIZE,
+            "crosses a page boundary"
+        );
+        // Program a word at a time like a real controller, through the `Words` helper.
+        for (i, word) in Words::<u32>::new(data, ERASED).enumerate() {
+            for (j, byte) in word.to_le_bytes().iter().enumerate() {
+                let Some(cell) = self.flash[range.clone()].get_mut(4 * i + j) else {
+                    break;
+                };
+                if *cell & byte != *byte {
+                    return Err(ErrorCode::new(ERR_NOT_ERASED).unwrap());
+                }
+                *cell &= byte;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FlashAlgorithm for Algorithm {
+    fn new(address: Addr, _clock: u32, _function: Function) -> Result<Self, ErrorCode> {
+        Self::range(address, 0)?;
+        Ok(Self {
+            flash: FLASH.with(|flash| flash.take()),
+        })
+    }
+
+    #[cfg(feature = "erase-chip")]
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        self.flash.fill(ERASED);
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, address: Addr) -> Result<(), ErrorCode> {
+        let range = Self::range(address, SECTOR_SIZE)?;
+        self.flash[range].fill(ERASED);
+        Ok(())
+    }
+
+    fn program_page(&mut self, address: Addr, data: &[u8]) -> Result<(), ErrorCode> {
+        Self::range(address, data.len())?;
+        // The host may send more than a page, split it like a real controller would.
+        for (address, data) in pages(address, data, PAGE_SIZE as u32) {
+            self.program(address, data)?;
+        }
+        Ok(())
+    }
+
+    fn verify(&mut self, address: Addr, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode> {
+        let range = Self::range(address, size as usize)?;
+        let blank = |address, size| Self::blank(&self.flash, address, size);
+        match data {
+            // Erased gaps of the image take the blank check path.
+            Some(data) => verify_sparse(
+                address,
+                &data[..size as usize],
+                ERASED,
+                PAGE_SIZE,
+                |address, expected| {
+                    let range = Self::range(address, expected.len())?;
+                    match self.flash[range] == *expected {
+                        true => Ok(()),
+                        false => Err(ErrorCode::new(ERR_MISMATCH).unwrap()),
+                    }
+                },
+                blank,
+            ),
+            None => blank(FLASH_ADDRESS + range.start as Addr, size),
+        }
+    }
+}
+
+impl Drop for Algorithm {
+    fn drop(&mut self) {
+        // Hand the contents back, like real flash keeps them across `UnInit`.
+        FLASH.with(|flash| flash.replace(std::mem::take(&mut self.flash)));
+    }
+}
+
+std::thread_local! {
+    /// The flash while no algorithm instance owns it.
+    static FLASH: std::cell::RefCell<Vec<u8>> = std::cell::RefCell::new(vec![0; FLASH_SIZE]);
+}
+
+/// Deterministic test data, so a failure can be reproduced. Some of it is left erased,
+/// in gaps that do not line up with pages, as in a sparse image.
+fn image(seed: u32) -> Vec<u8> {
+    let mut state = seed | 1;
+    (0..FLASH_SIZE - seed as usize % PAGE_SIZE)
+        .map(|i| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            match (i + seed as usize * 0x40) % 0x1800 < 0x500 {
+                true => ERASED,
+                false => state as u8,
+            }
+        })
+        .collect()
+}
+
+/// One erase, program and verify cycle, returning the first error.
+fn cycle(image: &[u8], erase_chip: bool) -> Result<(), ErrorCode> {
+    let mut algo = Algorithm::new(FLASH_ADDRESS, 0, Function::Erase)?;
+    if erase_chip && cfg!(feature = "erase-chip") {
+        #[cfg(feature = "erase-chip")]
+        algo.erase_all()?;
+    } else {
+        for sector in (0..FLASH_SIZE).step_by(SECTOR_SIZE) {
+            algo.erase_sector(FLASH_ADDRESS + sector as Addr)?;
+        }
+    }
+    algo.verify(FLASH_ADDRESS, FLASH_SIZE as u32, None)?;
+    drop(algo);
+
+    // Send a page and a half at a time, so most buffers start or end inside a page. The
+    // last one is usually partial, which exercises the padding in `Words`.
+    let mut algo = Algorithm::new(FLASH_ADDRESS, 0, Function::Program)?;
+    for (i, chunk) in image.chunks(CHUNK_SIZE).enumerate() {
+        algo.program_page(FLASH_ADDRESS + (i * CHUNK_SIZE) as Addr, chunk)?;
+    }
+    drop(algo);
+
+    let mut algo = Algorithm::new(FLASH_ADDRESS, 0, Function::Verify)?;
+    algo.verify(FLASH_ADDRESS, image.len() as u32, Some(image))?;
+    // A wrong byte has to be found both in an erased gap and in the programmed data.
+    let gap = image
+        .windows(PAGE_SIZE)
+        .position(|w| w.iter().all(|b| *b == ERASED));
+    let data = image.iter().position(|b| *b != ERASED);
+    for wrong in [gap.map(|gap| gap + PAGE_SIZE / 2), data]
+        .into_iter()
+        .flatten()
+    {
+        let mut expected = image.to_vec();
+        expected[wrong] ^= 1;
+        let result = algo.verify(FLASH_ADDRESS, image.len() as u32, Some(&expected));
+        assert!(result.is_err(), "wrong byte at {wrong:#x} not found");
+    }
+    let tail = FLASH_ADDRESS + image.len() as Addr;
+    algo.verify(tail, (FLASH_SIZE - image.len()) as u32, None)
+}
+
+fn main() {
+    for seed in 0..CYCLES {
+        let image = image(seed);
+        if let Err(e) = cycle(&image, seed % 2 == 0) {
+            panic!("cycle {seed} failed with error {e:#x}");
+        }
+        FLASH.with(|flash| {
+            let flash = flash.borrow();
+            assert_eq!(flash[..image.len()], image[..], "cycle {seed}");
+            assert!(flash[image.len()..].iter().all(|byte| *byte == ERASED));
+        });
+    }
+    println!("{CYCLES} cycles of {FLASH_SIZE:#x} bytes passed");
+}
@@ -0,0 +1,209 @@
+//! Flash algorithm for the internal flash of an STM32F4 with 1 MiB of flash.
+//!
+//! This shows the shape of a real algorithm: the controller is unlocked in `new`,
+//! every operation waits for the busy flag with a bounded number of polls and
+//! the controller error flags are reported back to the host as error codes.
+
+#![no_std]
+#![no_main]
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use flash_algorithm::{mmio, poll_until, ErrorCode, FlashAlgorithm, Function, Words};
+
+const FLASH_BASE: u32 = 0x0800_0000;
+const FLASH_SIZE: u32 = 0x10_0000;
+
+#[cfg(feature = "debug-dump")]
+const FLASH_ACR: usize = 0x4002_3C00;
+const FLASH_KEYR: usize = 0x4002_3C04;
+const FLASH_SR: usize = 0x4002_3C0C;
+const FLASH_CR: usize = 0x4002_3C10;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+const SR_BSY: u32 = 1 << 16;
+/// OPERR, WRPERR, PGAERR, PGPERR and PGSERR.
+const SR_ERRORS: u32 = 0xF2;
+
+const CR_PG: u32 = 1 << 0;
+const CR_SER: u32 = 1 << 1;
+const CR_MER: u32 = 1 << 2;
+const CR_SNB_SHIFT: u32 = 3;
+const CR_PSIZE_X32: u32 = 0b10 << 8;
+const CR_STRT: u32 = 1 << 16;
+const CR_LOCK: u32 = 1 << 31;
+
+/// Returned when the host asks for an address that is not in this flash.
+const ERR_ADDRESS: u32 = 0x100;
+
+struct Algorithm {
+    /// How many times the busy flag is polled before giving up.
+    max_polls: u32,
+}
+
+flash_algorithm::algorithm!(Algorithm, {
+    device_name: "stm32f4 1MiB",
+    device_type: DeviceType::Onchip,
+    flash_address: FLASH_BASE,
+    flash_size: FLASH_SIZE,
+    page_size: 0x400,
+    empty_value: 0xFF,
+    program_time_out: 1000,
+    erase_time_out: 4000,
+    load_address: 0x2000_0000,
+    // The controller programs one word at a time.
+    write_align: 4,
+    // The maximum erase times from the datasheet, at x32 parallelism.
+    sectors: [{
+        size: 0x4000,
+        address: 0x0,
+        erase_time_out: 500,
+    }, {
+        size: 0x1_0000,
+        address: 0x1_0000,
+        erase_time_out: 1100,
+    }, {
+        size: 0x2_0000,
+        address: 0x2_0000,
+        erase_time_out: 2000,
+    }]
+});
+
+impl Algorithm {
+    /// Wait until the controller is idle and turn any error flags into an error code.
+    fn wait_ready(&self) -> Result<(), ErrorCode> {
+        poll_until(
+            || unsafe { mmio::read32(FLASH_SR) } & SR_BSY == 0,
+            self.max_polls,
+        )?;
+        let status = unsafe { mmio::read32(FLASH_SR) };
+        let errors = status & SR_ERRORS;
+        // The error flags are cleared by writing ones to them.
+        unsafe { mmio::write32(FLASH_SR, errors) };
+        match ErrorCode::new(errors) {
+            None => Ok(()),
+            #[cfg(feature = "vendor-status")]
+            Some(e) => Err(flash_algorithm::vendor_error(e, status)),
+            #[cfg(not(feature = "vendor-status"))]
+            Some(e) => Err(e),
+        }
+    }
+
+    /// Map an address to the number of the sector containing it.
+    fn sector_number(address: u32) -> Result<u32, ErrorCode> {
+        let offset = address
+            .checked_sub(FLASH_BASE)
+            .filter(|offset| *offset < FLASH_SIZE)
+            .ok_or(ErrorCode::new(ERR_ADDRESS).unwrap())?;
+        Ok(match offset {
+            0..=0xFFFF => offset / 0x4000,
+            0x1_0000..=0x1_FFFF => 4,
+            _ => 4 + offset / 0x2_0000,
+        })
+    }
+}
+
+impl FlashAlgorithm for Algorithm {
+    fn new(_address: u32, clock: u32, _function: Function) -> Result<Self, ErrorCode> {
+        unsafe {
+            if mmio::read32(FLASH_CR) & CR_LOCK != 0 {
+                mmio::write32(FLASH_KEYR, KEY1);
+                mmio::write32(FLASH_KEYR, KEY2);
+            }
+        }
+        // A 128 KiB sector takes up to four seconds to erase and a poll takes
+        // a handful of cycles, so this budget covers the worst case at any clock.
+        let this = Self {
+            max_polls: clock.max(16_000_000),
+        };
+        this.wait_ready()?;
+        Ok(this)
+    }
+
+    #[cfg(feature = "erase-chip")]
+    fn erase_all(&mut self) -> Result<(), ErrorCode> {
+        unsafe {
+            mmio::write32(FLASH_CR, CR_MER);
+            mmio::write32(FLASH_CR, CR_MER | CR_STRT);
+        }
+        let result = self.wait_ready();
+        unsafe { mmio::write32(FLASH_CR, 0) };
+        result
+    }
+
+    fn erase_sector(&mut self, address: u32) -> Result<(), ErrorCode> {
+        let cr = CR_SER | CR_PSIZE_X32 | (Self::sector_number(address)? << CR_SNB_SHIFT);
+        unsafe {
+            mmio::write32(FLASH_CR, cr);
+            mmio::write32(FLASH_CR, cr | CR_STRT);
+        }
+        let result = self.wait_ready();
+        unsafe { mmio::write32(FLASH_CR, 0) };
+        result
+    }
+
+    fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        Self::sector_number(address)?;
+        unsafe { mmio::write32(FLASH_CR, CR_PG | CR_PSIZE_X32) };
+        let mut result = Ok(());
+        // A trailing partial word is padded with the erased value.
+        for (i, word) in Words::<u32>::new(data, 0xFF).enumerate() {
+            let target = (address + 4 * i as u32) as usize;
+            unsafe { mmio::write32(target, word) };
+            result = self.wait_ready();
+            if result.is_err() {
+                break;
+            }
+        }
+        unsafe { mmio::write32(FLASH_CR, 0) };
+        result
+    }
+
+    #[cfg(feature = "verify")]
+    fn verify(&mut self, address: u32, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode> {
+        // The flash is memory mapped, so it can be compared in place.
+        let mut result = Ok(());
+        for offset in 0..size {
+            let actual = unsafe { core::ptr::read_volatile((address + offset) as *const u8) };
+            let expected = data.map_or(0xFF, |data| data[offset as usize]);
+            if actual != expected {
+                // Report the first mismatch, but keep going to record the others.
+                if result.is_ok() {
+                    result = Err(ErrorCode::new(address + offset).unwrap_or(ErrorCode::MIN));
+                }
+                #[cfg(feature = "verify-diag")]
+                flash_algorithm::record_mismatch(offset);
+                #[cfg(not(feature = "verify-diag"))]
+                break;
+            }
+        }
+        result
+    }
+
+    #[cfg(feature = "debug-dump")]
+    fn dump_config(&mut self, out: &mut [u8]) -> Result<(), ErrorCode> {
+        // ACR, KEYR, OPTKEYR, SR, CR and OPTCR. The key registers are write only and
+        // read as 0, but keep the offsets the same as in the reference manual.
+        for (i, chunk) in out.chunks_exact_mut(4).take(6).enumerate() {
+            let value = unsafe { mmio::read32(FLASH_ACR + 4 * i) };
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "abort")]
+    fn abort(&mut self) {
+        // A started erase or program cannot be stopped, so let it finish and leave
+        // the controller idle with no operation selected and the error flags cleared.
+        let _ = self.wait_ready();
+        unsafe { mmio::write32(FLASH_CR, 0) };
+    }
+}
+
+impl Drop for Algorithm {
+    fn drop(&mut self) {
+        // Lock the controller again when the host calls `UnInit`.
+        unsafe { mmio::write32(FLASH_CR, CR_LOCK) };
+    }
+}
@@ -32,6 +32,9 @@ SECTIONS {
         *(.uninit)
         *(.uninit.*)
 
+        /* Last, so an overflowing stack above the algorithm hits it first (`stack-guard` feature) */
+        KEEP(*(.stack_guard))
+
         . = ALIGN(4);
     }
 
@@ -56,9 +59,21 @@ SECTIONS {
          *
          * The KEEP statement ensures it's not removed by accident.
          */
+        KEEP(*(DeviceData.header)) /* First, where a host looks for it (`descriptor-header` feature) */
         KEEP(*(DeviceData))
     }
 
+    /* Reset stub for bring-up without a host, never in a blob (`standalone` feature) */
+    Standalone ALIGN(128) : {
+        KEEP(*(.standalone.vectors))
+    }
+
+    /* Human readable geometry, only for external tools (`geometry-note` feature).
+     * INFO keeps it out of the loadable segments, so it is never copied to the target. */
+    GeometryNote (INFO) : {
+        KEEP(*(GeometryNote))
+    }
+
     /DISCARD/ : {
         /* Unused exception related info that only wastes space */
         *(.ARM.exidx);
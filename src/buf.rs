@@ -0,0 +1,116 @@
+//! Checked copies into the aligned buffers and FIFOs a flash controller is fed from.
+//!
+//! Also the length check for program data, see [`check_program_len()`].
+
+use crate::{ErrorCode, ERR_ALIGN, ERR_LENGTH};
+
+/// Copy `src` to the start of `dst`, which has to be at least as long and start at a
+/// multiple of `ALIGN` bytes.
+///
+/// Returns [`ERR_LENGTH`] if `src` does not fit and [`ERR_ALIGN`] if `dst` is not
+/// aligned, without writing anything. `ALIGN` has to be a power of two.
+///
+/// ```ignore
+/// // Pad a partial page with the erased value.
+/// fill_aligned::<4>(&mut self.page_buffer, PAGE_SIZE, 0xFF)?;
+/// copy_to_aligned::<4>(&mut self.page_buffer, data)?;
+/// ```
+pub fn copy_to_aligned<const ALIGN: usize>(dst: &mut [u8], src: &[u8]) -> Result<(), ErrorCode> {
+    check_align::<ALIGN>(dst)?;
+    copy(dst, src)
+}
+
+/// Set the first `len` bytes of `dst`, which has to start at a multiple of `ALIGN`
+/// bytes, to `value`.
+///
+/// Returns [`ERR_LENGTH`] if `dst` is shorter than `len` and [`ERR_ALIGN`] if it is not
+/// aligned, without writing anything. `ALIGN` has to be a power of two.
+pub fn fill_aligned<const ALIGN: usize>(
+    dst: &mut [u8],
+    len: usize,
+    value: u8,
+) -> Result<(), ErrorCode> {
+    check_align::<ALIGN>(dst)?;
+    fill(dst, len, value)
+}
+
+/// Check that `len` bytes of program data are a whole number of the `granularity` bytes
+/// the controller writes at once, and return [`ERR_LENGTH`] otherwise.
+///
+/// A controller that writes whole words or double words would otherwise write
+/// whatever follows the data in the buffer as the last one. With the `strict-align`
+/// feature `ProgramPage` and `ProgramAndVerify` already do this check against the
+/// `min_program_size` of [`algorithm!`](crate::algorithm!).
+///
+/// ```ignore
+/// fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode> {
+///     check_program_len(data.len(), 8)?;
+///     // ..
+/// }
+/// ```
+pub const fn check_program_len(len: usize, granularity: u32) -> Result<(), ErrorCode> {
+    if len.is_multiple_of(granularity as usize) {
+        Ok(())
+    } else {
+        Err(ERR_LENGTH)
+    }
+}
+
+fn check_align<const ALIGN: usize>(dst: &[u8]) -> Result<(), ErrorCode> {
+    const { assert!(ALIGN.is_power_of_two(), "`ALIGN` must be a power of two") };
+    if dst.as_ptr() as usize & (ALIGN - 1) == 0 {
+        Ok(())
+    } else {
+        Err(ERR_ALIGN)
+    }
+}
+
+const fn copy(dst: &mut [u8], src: &[u8]) -> Result<(), ErrorCode> {
+    match dst.split_at_mut_checked(src.len()) {
+        Some((head, _)) => {
+            head.copy_from_slice(src);
+            Ok(())
+        }
+        None => Err(ERR_LENGTH),
+    }
+}
+
+const fn fill(dst: &mut [u8], len: usize, value: u8) -> Result<(), ErrorCode> {
+    let Some((head, _)) = dst.split_at_mut_checked(len) else {
+        return Err(ERR_LENGTH);
+    };
+    let mut i = 0;
+    while i < head.len() {
+        head[i] = value;
+        i += 1;
+    }
+    Ok(())
+}
+
+const fn is_err(result: Result<(), ErrorCode>, code: ErrorCode) -> bool {
+    matches!(result, Err(e) if e.get() == code.get())
+}
+
+// Too long data is rejected without touching the buffer, anything that fits is copied
+// to the start and leaves the rest alone.
+const _: () = {
+    let mut buf = [0u8; 4];
+    assert!(is_err(copy(&mut buf, &[1, 2, 3, 4, 5]), ERR_LENGTH));
+    assert!(is_err(fill(&mut buf, 5, 0xFF), ERR_LENGTH));
+    assert!(buf[0] == 0 && buf[3] == 0);
+    assert!(copy(&mut buf, &[1, 2, 3]).is_ok());
+    assert!(buf[0] == 1 && buf[2] == 3 && buf[3] == 0);
+    assert!(fill(&mut buf, 2, 0xFF).is_ok());
+    assert!(buf[0] == 0xFF && buf[1] == 0xFF && buf[2] == 3);
+    assert!(copy(&mut buf, &[]).is_ok() && fill(&mut [], 0, 0).is_ok());
+};
+
+// Exact multiples of the granularity pass, anything shorter or in between fails.
+const _: () = {
+    assert!(check_program_len(8, 8).is_ok());
+    assert!(check_program_len(32, 8).is_ok());
+    assert!(check_program_len(0, 8).is_ok());
+    assert!(is_err(check_program_len(4, 8), ERR_LENGTH));
+    assert!(is_err(check_program_len(12, 8), ERR_LENGTH));
+    assert!(check_program_len(7, 1).is_ok());
+};
@@ -0,0 +1,64 @@
+//! Counters of what the algorithm did, for the host to read after a flashing session.
+
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+
+/// The type of [`COUNTERS`].
+///
+/// The host finds it through the `COUNTERS` symbol. It is `#[repr(C)]`, so each counter
+/// is a little endian `u32` at a fixed offset:
+///
+/// | Offset | Counter            |
+/// |--------|--------------------|
+/// | 0      | `sectors_erased`   |
+/// | 4      | `chip_erases`      |
+/// | 8      | `pages_programmed` |
+/// | 12     | `retries`          |
+/// | 16     | `timeouts`         |
+///
+/// New counters are only ever added at the end. They start at 0 when the algorithm is
+/// loaded, are never reset by the algorithm and wrap around on overflow. A host that
+/// wants the numbers of a single session clears them before `Init`.
+#[repr(C)]
+pub struct Counters {
+    /// `EraseSector` calls that succeeded.
+    pub sectors_erased: AtomicU32,
+    /// `EraseChip` calls that succeeded.
+    pub chip_erases: AtomicU32,
+    /// `ProgramPage` and `ProgramAndVerify` calls that succeeded.
+    pub pages_programmed: AtomicU32,
+    /// Operations the algorithm retried, see [`count_retry()`].
+    pub retries: AtomicU32,
+    /// Timeouts reported by [`poll_until()`](crate::poll_until), or by `timeout()` with
+    /// `timeout-report`.
+    pub timeouts: AtomicU32,
+}
+
+const _: () = assert!(core::mem::size_of::<Counters>() == 20);
+
+/// Diagnostic counters that the entry points and helpers of this crate increment.
+#[no_mangle]
+pub static COUNTERS: Counters = Counters {
+    sectors_erased: AtomicU32::new(0),
+    chip_erases: AtomicU32::new(0),
+    pages_programmed: AtomicU32::new(0),
+    retries: AtomicU32::new(0),
+    timeouts: AtomicU32::new(0),
+};
+
+/// Count a retried operation in [`Counters::retries`], for algorithms that retry on
+/// their own.
+///
+/// ```ignore
+/// while let Err(e) = self.program_word(address, word) {
+///     flash_algorithm::count_retry();
+///     // ..
+/// }
+/// ```
+pub fn count_retry() {
+    increment(&COUNTERS.retries);
+}
+
+pub(crate) fn increment(counter: &AtomicU32) {
+    // Entry points are never entered concurrently, a load and store is enough.
+    counter.store(counter.load(Relaxed).wrapping_add(1), Relaxed);
+}
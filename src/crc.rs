@@ -0,0 +1,58 @@
+/// The CRC-32 used by Ethernet, zlib and PNG (reflected polynomial `0xEDB88320`,
+/// initial value and final XOR `0xFFFF_FFFF`).
+///
+/// It is computed bit by bit to keep the algorithm blob small.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    /// Feed more bytes into the checksum.
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.update_byte(*byte);
+        }
+    }
+
+    /// Feed a single byte into the checksum.
+    pub const fn update_byte(&mut self, byte: u8) {
+        self.0 ^= byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            let mask = (self.0 & 1).wrapping_neg();
+            self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            bit += 1;
+        }
+    }
+
+    /// The checksum of `data`, also at compile time.
+    pub const fn of(data: &[u8]) -> u32 {
+        let mut crc = Self::new();
+        let mut i = 0;
+        while i < data.len() {
+            crc.update_byte(data[i]);
+            i += 1;
+        }
+        crc.finish()
+    }
+
+    /// The checksum of all bytes fed so far.
+    pub const fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The standard check value, and 0 for no data.
+const _: () = {
+    assert!(Crc32::of(b"123456789") == 0xCBF4_3926);
+    assert!(Crc32::of(b"") == 0);
+};
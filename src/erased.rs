@@ -0,0 +1,88 @@
+//! Checking that a region is erased.
+
+/// The offset of the first byte of `bytes` that is not `erased`, or `None` if they all
+/// are.
+///
+/// [`verify_erased_mapped()`] uses this on the memory mapped flash, an override of
+/// [`FlashAlgorithm::verify_erased()`](crate::FlashAlgorithm::verify_erased) can use it
+/// on bytes it read some other way.
+pub const fn first_not_erased(bytes: &[u8], erased: u8) -> Option<usize> {
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != erased {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Check that `size` bytes at `address` are `erased` by reading them through the memory
+/// map, and return [`ERR_NOT_ERASED`](crate::ERR_NOT_ERASED) if one is not, or
+/// [`ERR_ADDRESS`](crate::ERR_ADDRESS) if the core cannot address the region.
+///
+/// For an override of
+/// [`FlashAlgorithm::verify_erased()`](crate::FlashAlgorithm::verify_erased) on flash
+/// that is memory mapped:
+///
+/// ```ignore
+/// fn verify_erased(&mut self, address: u32, size: u32, erased: u8) -> Result<(), ErrorCode> {
+///     unsafe { flash_algorithm::verify_erased_mapped(address, size, erased) }
+/// }
+/// ```
+///
+/// # Safety
+///
+/// The whole region has to be readable memory, like flash that the controller maps
+/// into the address space.
+#[cfg(feature = "verify")]
+pub unsafe fn verify_erased_mapped(
+    address: crate::Addr,
+    size: u32,
+    erased: u8,
+) -> Result<(), crate::ErrorCode> {
+    let region = unsafe { mapped(address, size)? };
+    match first_not_erased(region, erased) {
+        None => Ok(()),
+        Some(_) => Err(crate::ERR_NOT_ERASED),
+    }
+}
+
+/// The `size` bytes at `address` in the memory map, or [`ERR_ADDRESS`](crate::ERR_ADDRESS)
+/// if they do not fit in the address space of the core, instead of truncating `address`.
+///
+/// # Safety
+///
+/// The whole region has to be readable memory.
+#[cfg(any(feature = "verify", feature = "blank-query"))]
+pub(crate) unsafe fn mapped<'a>(
+    address: crate::Addr,
+    size: u32,
+) -> Result<&'a [u8], crate::ErrorCode> {
+    let start = usize::try_from(address).map_err(|_| crate::ERR_ADDRESS)?;
+    // A region may end at the top of the address space, but not wrap around it.
+    if size != 0 && start.checked_add(size as usize - 1).is_none() {
+        return Err(crate::ERR_ADDRESS);
+    }
+    Ok(unsafe { core::slice::from_raw_parts(start as *const u8, size as usize) })
+}
+
+const fn is(bytes: &[u8], erased: u8, expected: Option<usize>) -> bool {
+    match (first_not_erased(bytes, erased), expected) {
+        (Some(offset), Some(expected)) => offset == expected,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// A fully erased region passes, a partially written one fails at its first written
+// byte, and the erased value is taken as given.
+const _: () = {
+    assert!(is(&[0xFF; 16], 0xFF, None));
+    assert!(is(&[], 0xFF, None));
+    assert!(is(&[0xFF, 0xFF, 0x7F, 0x00], 0xFF, Some(2)));
+    assert!(is(&[0x00, 0xFF], 0xFF, Some(0)));
+    assert!(is(&[0xFF, 0xFF, 0xFF, 0xFE], 0xFF, Some(3)));
+    assert!(is(&[0x00; 4], 0x00, None));
+    assert!(is(&[0x00; 4], 0xFF, Some(0)));
+};
@@ -0,0 +1,66 @@
+//! Saving the floating point state of the halted application around an entry point.
+
+use core::mem::MaybeUninit;
+
+#[cfg(not(any(feature = "std", target_abi = "eabihf")))]
+compile_error!("`fp-save` is for hard-float targets, such as `thumbv7em-none-eabihf`");
+
+/// The registers that an `extern "C"` function may clobber without restoring them under
+/// the hard-float ABI, `s0`-`s15` and then `FPSCR`. `s16`-`s31` are callee-saved, the
+/// compiler already restores them in every function that uses them.
+///
+/// Every entry point with `fp-save` creates one and saves the registers into it in place,
+/// before anything else, so no copy of it can touch them first. They are restored when it
+/// is dropped on return.
+#[doc(hidden)]
+#[cfg_attr(not(all(target_arch = "arm", target_abi = "eabihf")), allow(dead_code))]
+pub struct FpState(MaybeUninit<[u32; 17]>);
+
+impl FpState {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self(MaybeUninit::uninit())
+    }
+
+    #[inline(always)]
+    pub fn save(&mut self) {
+        #[cfg(all(target_arch = "arm", target_abi = "eabihf"))]
+        unsafe {
+            core::arch::asm!(
+                "vstmia {regs}, {{s0-s15}}",
+                "vmrs {fpscr}, fpscr",
+                "str {fpscr}, [{regs}, #64]",
+                regs = in(reg) self.0.as_mut_ptr(),
+                fpscr = out(reg) _,
+                options(nostack, preserves_flags),
+            );
+        }
+    }
+}
+
+impl Drop for FpState {
+    #[inline(always)]
+    fn drop(&mut self) {
+        #[cfg(all(target_arch = "arm", target_abi = "eabihf"))]
+        unsafe {
+            core::arch::asm!(
+                "ldr {fpscr}, [{regs}, #64]",
+                "vmsr fpscr, {fpscr}",
+                "vldmia {regs}, {{s0-s15}}",
+                regs = in(reg) self.0.as_ptr(),
+                fpscr = out(reg) _,
+                out("s0") _, out("s1") _, out("s2") _, out("s3") _,
+                out("s4") _, out("s5") _, out("s6") _, out("s7") _,
+                out("s8") _, out("s9") _, out("s10") _, out("s11") _,
+                out("s12") _, out("s13") _, out("s14") _, out("s15") _,
+                options(nostack, preserves_flags),
+            );
+        }
+    }
+}
+
+impl Default for FpState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
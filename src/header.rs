@@ -0,0 +1,72 @@
+//! A small header at the start of `DeviceData` that identifies a `flash-algorithm` blob.
+
+/// The first bytes of [`DescriptorHeader`].
+pub const DESCRIPTOR_MAGIC: [u8; 4] = *b"FLA1";
+
+/// Emitted as `FlashAlgorithmHeader` at the very start of the `DeviceData` section.
+/// With several algorithms in one blob their headers come first, one after another.
+///
+/// `version` and `device_type` repeat the `vers` and `dev_type` of `FlashDevice`, so a
+/// host can tell whether it is looking at a descriptor at all before parsing one. The
+/// `FlashDevice` that follows keeps its CMSIS-Pack layout and is still found through its
+/// symbol. The header is 8 bytes, which keeps the 8 byte alignment `addr64` needs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct DescriptorHeader {
+    pub magic: [u8; 4],
+    pub version: u16,
+    pub device_type: u16,
+}
+
+const _: () = assert!(core::mem::size_of::<DescriptorHeader>() == 8);
+
+/// Why [`DescriptorHeader::parse()`] rejected some bytes.
+#[cfg(feature = "std")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    /// There are fewer than 8 bytes.
+    Truncated,
+    /// The bytes do not start with [`DESCRIPTOR_MAGIC`].
+    BadMagic([u8; 4]),
+}
+
+impl DescriptorHeader {
+    #[doc(hidden)]
+    pub const fn new(version: u16, device_type: u16) -> Self {
+        Self {
+            magic: DESCRIPTOR_MAGIC,
+            version,
+            device_type,
+        }
+    }
+
+    /// Decode the header from the start of the `DeviceData` section, as a host would.
+    #[cfg(feature = "std")]
+    pub fn parse(data: &[u8]) -> Result<Self, HeaderError> {
+        let Some(data) = data.get(..8) else {
+            return Err(HeaderError::Truncated);
+        };
+        let magic: [u8; 4] = data[..4].try_into().unwrap();
+        if magic != DESCRIPTOR_MAGIC {
+            return Err(HeaderError::BadMagic(magic));
+        }
+        Ok(Self {
+            magic,
+            version: u16::from_le_bytes([data[4], data[5]]),
+            device_type: u16::from_le_bytes([data[6], data[7]]),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "the descriptor header is truncated"),
+            Self::BadMagic(magic) => write!(f, "bad descriptor magic {magic:02x?}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HeaderError {}
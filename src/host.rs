@@ -0,0 +1,212 @@
+//! Calling the entry points of an algorithm built for the host, the way a debug probe
+//! host calls them on the target.
+//!
+//! With the `std` feature [`algorithm!`](crate::algorithm!) still emits the
+//! `extern "C"` entry points, so a test can run an algorithm in-process against a flash
+//! simulated in RAM, through the same ABI and error codes probe-rs sees, instead of
+//! calling the [`FlashAlgorithm`](crate::FlashAlgorithm) methods directly. [`Host`]
+//! wraps the entry points to take slices and return results:
+//!
+//! ```ignore
+//! let mut host = unsafe { Host::new(flash_algorithm::entry_points!()) };
+//! host.init(FLASH_ADDRESS, 0, Function::Erase)?;
+//! host.erase_sector(FLASH_ADDRESS)?;
+//! host.uninit()?;
+//! host.init(FLASH_ADDRESS, 0, Function::Program)?;
+//! host.program_page(FLASH_ADDRESS, &page)?;
+//! host.uninit()?;
+//! ```
+//!
+//! See `examples/simulate.rs` for a complete session.
+
+use crate::{Addr, ErrorCode, Function};
+
+/// The entry points of one algorithm, as emitted by [`algorithm!`](crate::algorithm!).
+///
+/// [`entry_points!`](crate::entry_points!) fills this in from the names in scope.
+/// `erase_chip` and `verify` are `None` without the `erase-chip` and `verify` features.
+pub struct EntryPoints {
+    pub init: unsafe extern "C" fn(Addr, u32, u32) -> u32,
+    pub uninit: unsafe extern "C" fn() -> u32,
+    pub erase_chip: Option<unsafe extern "C" fn() -> u32>,
+    pub erase_sector: unsafe extern "C" fn(Addr) -> u32,
+    pub program_page: unsafe extern "C" fn(Addr, u32, *const u8) -> u32,
+    pub verify: Option<unsafe extern "C" fn(Addr, u32, *const u8) -> u32>,
+}
+
+/// Calls the entry points of an algorithm like a host would, turning the status codes
+/// they return into results.
+///
+/// A status of 0 is `Ok`, anything else the `Err` with that code, including the 1 that
+/// the entry points return when the algorithm is not initialized.
+pub struct Host {
+    entry_points: EntryPoints,
+}
+
+impl Host {
+    /// Wrap the entry points of an algorithm.
+    ///
+    /// # Safety
+    ///
+    /// The entry points keep the instance of the algorithm in statics, so nothing else
+    /// may call them while the `Host` is in use, including another `Host` on another
+    /// thread.
+    pub unsafe fn new(entry_points: EntryPoints) -> Self {
+        Self { entry_points }
+    }
+
+    pub fn init(&mut self, address: Addr, clock: u32, function: Function) -> Result<(), ErrorCode> {
+        status(unsafe { (self.entry_points.init)(address, clock, function as u32) })
+    }
+
+    pub fn uninit(&mut self) -> Result<(), ErrorCode> {
+        status(unsafe { (self.entry_points.uninit)() })
+    }
+
+    /// Call `EraseChip`.
+    ///
+    /// # Panics
+    ///
+    /// If the algorithm was built without the `erase-chip` feature.
+    pub fn erase_chip(&mut self) -> Result<(), ErrorCode> {
+        let erase_chip = self
+            .entry_points
+            .erase_chip
+            .expect("no `EraseChip` entry point");
+        status(unsafe { erase_chip() })
+    }
+
+    pub fn erase_sector(&mut self, address: Addr) -> Result<(), ErrorCode> {
+        status(unsafe { (self.entry_points.erase_sector)(address) })
+    }
+
+    pub fn program_page(&mut self, address: Addr, data: &[u8]) -> Result<(), ErrorCode> {
+        status(unsafe {
+            (self.entry_points.program_page)(address, data.len() as u32, data.as_ptr())
+        })
+    }
+
+    /// Call `Verify`, without data for `None`. With `verify-crc` `Verify` computes a
+    /// checksum instead of checking that the region is erased when there is no data,
+    /// see [`Host::verify_crc()`].
+    ///
+    /// With `verify-progress` this is `Ok` if `Verify` returned `address + size`, and
+    /// otherwise the error in `VERIFY_STATUS`, or
+    /// [`ERR_VERIFY_INCOMPLETE`](crate::ERR_VERIFY_INCOMPLETE) if there is none. See
+    /// [`Host::verify_progress()`] for the address it returned.
+    ///
+    /// # Panics
+    ///
+    /// If the algorithm was built without the `verify` feature.
+    pub fn verify(
+        &mut self,
+        address: Addr,
+        size: u32,
+        data: Option<&[u8]>,
+    ) -> Result<(), ErrorCode> {
+        #[cfg(not(feature = "verify-progress"))]
+        return status(self.call_verify(address, size, data));
+        #[cfg(feature = "verify-progress")]
+        match self.verify_progress(address, size, data) {
+            (end, _) if end == address.wrapping_add(size) => Ok(()),
+            (_, status) => Err(ErrorCode::new(status).unwrap_or(crate::ERR_VERIFY_INCOMPLETE)),
+        }
+    }
+
+    /// Call `Verify` and return the address it returned together with `VERIFY_STATUS`,
+    /// see [Verify progress](crate#verify-progress).
+    ///
+    /// # Panics
+    ///
+    /// If the algorithm was built without the `verify` feature.
+    #[cfg(feature = "verify-progress")]
+    pub fn verify_progress(
+        &mut self,
+        address: Addr,
+        size: u32,
+        data: Option<&[u8]>,
+    ) -> (Addr, u32) {
+        let end = self.call_verify(address, size, data);
+        let status = crate::VERIFY_STATUS.load(core::sync::atomic::Ordering::Relaxed);
+        (end, status)
+    }
+
+    /// Call `Verify` without data and return the checksum it stored in `VERIFY_CRC`.
+    ///
+    /// # Panics
+    ///
+    /// If the algorithm was built without the `verify` feature.
+    #[cfg(feature = "verify-crc")]
+    pub fn verify_crc(&mut self, address: Addr, size: u32) -> Result<u32, ErrorCode> {
+        self.verify(address, size, None)?;
+        Ok(crate::VERIFY_CRC.load(core::sync::atomic::Ordering::Relaxed))
+    }
+
+    fn call_verify(&mut self, address: Addr, size: u32, data: Option<&[u8]>) -> u32 {
+        let verify = self.entry_points.verify.expect("no `Verify` entry point");
+        if let Some(data) = data {
+            assert!(data.len() >= size as usize, "`data` is shorter than `size`");
+        }
+        let data = data.map_or(core::ptr::null(), <[u8]>::as_ptr);
+        unsafe { verify(address, size, data) }
+    }
+}
+
+/// Turn the status code of an entry point into a result.
+pub fn status(code: u32) -> Result<(), ErrorCode> {
+    match ErrorCode::new(code) {
+        None => Ok(()),
+        Some(e) => Err(e),
+    }
+}
+
+/// The [`EntryPoints`] emitted by [`algorithm!`](crate::algorithm!) in the current
+/// scope, for [`Host::new()`]. Use it in the module that invoked `algorithm!`, without a
+/// `symbol_prefix`.
+#[macro_export]
+macro_rules! entry_points {
+    () => {
+        $crate::host::EntryPoints {
+            init: Init,
+            uninit: UnInit,
+            erase_chip: $crate::host_erase_chip!(),
+            erase_sector: EraseSector,
+            program_page: ProgramPage,
+            verify: $crate::host_verify!(),
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "erase-chip"))]
+macro_rules! host_erase_chip {
+    () => {
+        None
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "erase-chip")]
+macro_rules! host_erase_chip {
+    () => {
+        Some(EraseChip)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "verify"))]
+macro_rules! host_verify {
+    () => {
+        None
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "verify")]
+macro_rules! host_verify {
+    () => {
+        Some(Verify)
+    };
+}
@@ -6,18 +6,406 @@
 //!
 //! - `panic-handler` this is enabled by default and includes a simple abort-on-panic
 //!   panic handler. Disable this feature flag if you would prefer to use a different
-//!   handler.
+//!   handler, either your own `#[panic_handler]` or a function passed to
+//!   [`panic_handler!`].
+//! - `abort` adds an `Abort` entry point that the host calls after cancelling an
+//!   operation, see [`FlashAlgorithm::abort()`].
+//! - `addr64` widens flash addresses to 64 bits for external memories larger than 4 GiB,
+//!   see [Addresses above 4 GiB](#addresses-above-4-gib).
+//! - `blank-check` adds the `BlankCheck` entry point and [`Function::Blank`], so the
+//!   algorithm can prepare the controller for blank checking in [`FlashAlgorithm::new()`].
+//! - `blank-query` adds an `IsRangeBlank(addr, size, blank)` entry point that tells the
+//!   host whether a region is erased without treating anything else as an error, so it
+//!   can skip erasing it, see [`FlashAlgorithm::is_range_blank()`].
+//! - `cmse` makes the entry points non-secure callable on Armv8-M with the Security
+//!   Extension, see [TrustZone](#trustzone).
+//! - `config-region` routes operations on an option byte region to dedicated methods,
+//!   see the `config_region` field of [`algorithm!`].
+//! - `counters` exposes [`COUNTERS`], counts of erased sectors, programmed pages,
+//!   retries and timeouts that the host can read at any time, see [`Counters`] for the
+//!   layout.
+//! - `debug-dump` adds a `DumpConfig(data, size)` entry point that fills the host's
+//!   buffer with a snapshot of the controller registers, see
+//!   [`FlashAlgorithm::dump_config()`].
+//! - `description` emits the `description` of [`algorithm!`], a text of any length for
+//!   host tools to display, as the `FlashAlgorithmDescription` symbol.
+//! - `descriptor-header` emits a [`DescriptorHeader`] with a magic value, the
+//!   descriptor version and the device type as `FlashAlgorithmHeader` at the start of
+//!   the `DeviceData` section, so a host can check that it found a descriptor before
+//!   parsing `FlashDevice`. With `std` it can validate it with
+//!   [`DescriptorHeader::parse()`].
+//! - `feature-manifest` emits the names of the enabled features into the `DeviceData`
+//!   section as the `FlashAlgorithmFeatures` symbol, see [`FEATURE_MANIFEST`]. Unlike
+//!   the `Capabilities` entry point it can be read from the ELF without running it.
+//! - `fp-save` saves the floating point registers that the hard-float ABI lets a function
+//!   clobber when an entry point is entered and restores them before it returns, see
+//!   [Floating point](#floating-point).
+//! - `geometry-note` additionally emits the geometry as `key=value` lines of text into
+//!   the `GeometryNote` section, so tools can inspect it without knowing the layout of
+//!   `FlashDevice`. Like `DeviceData` it is never loaded to the target.
+//! - `init-args` keeps the `address` and `clock` arguments of `Init` for the methods of
+//!   the algorithm to read with [`init_address()`] and [`init_clock()`], so it does not
+//!   need fields of its own for them.
+//! - `minimal-descriptor` emits a `FlashDevice` descriptor that only holds the sector
+//!   list, for hosts that know the rest of the geometry from elsewhere, see
+//!   [Minimal descriptor](#minimal-descriptor).
+//! - `name-guard` makes `Init` check that the host loaded the algorithm it meant to, and
+//!   return [`ERR_NAME`] before doing anything else if not. The host writes the
+//!   [`Crc32`] of the UTF-8 bytes of the `device_name` it expects, without a terminating
+//!   NUL, to the `u32` symbol `ExpectedNameHash` before every `Init`, and `Init`
+//!   compares it with the hash of the `device_name` of [`algorithm!`]. A host that does
+//!   not know about it leaves it 0 and cannot use the algorithm. `Crc32::of()` computes
+//!   the hash on a Rust host.
+//! - `page-index` passes the index of the page within the flash to
+//!   [`FlashAlgorithm::program_page()`], computed from `flash_address` and `page_size`,
+//!   which cannot be 0 then.
+//! - `program-verify` adds a `ProgramAndVerify` entry point that programs a page and
+//!   verifies it in a single call. It implies `verify`.
+//! - `retry` makes `ProgramPage` retry [`FlashAlgorithm::program_page()`] after the
+//!   errors [`FlashAlgorithm::is_transient()`] accepts, up to the `program_retries` of
+//!   [`algorithm!`] times. With `counters` every retry is counted.
+//! - `rtt-minimal` adds a dependency free SEGGER RTT control block with a single up
+//!   channel that [`log_bytes()`] and [`hexdump()`] write to. Any RTT host, including
+//!   probe-rs, can read it.
+//! - `scratch-buffer` passes a statically allocated scratch buffer to
+//!   [`FlashAlgorithm::new()`]. Its size is set with the `scratch_size` field of
+//!   [`algorithm!`].
+//! - `sector-size` passes the size of the sector being erased to
+//!   [`FlashAlgorithm::erase_sector()`], looked up in the `sectors` of [`algorithm!`].
+//!   Erasing an address that is in no sector fails with [`ERR_ADDRESS`].
+//! - `self-test` makes `Init` call [`FlashAlgorithm::self_test()`] on the new instance
+//!   and fail with its error.
+//! - `stack-guard` places a sentinel at the bottom of the stack, writes it in `Init` and
+//!   makes `UnInit` return [`ERR_STACK_OVERFLOW`] if it was overwritten, see
+//!   [`check_stack()`]. This relies on the host putting the stack directly above the
+//!   algorithm, as probe-rs does, and on the `PrgCode` section of `memory.x`.
+//! - `standalone` adds a reset stub that calls `Init` without a host, for bringing up an
+//!   algorithm on new hardware only, see [Standalone](#standalone). Never enable it for
+//!   a blob.
+//! - `stateless` is for algorithms without state, whose type is zero sized and does not
+//!   implement `Drop`. `Init` still calls [`FlashAlgorithm::new()`], but the value is
+//!   not stored anywhere and `UnInit` has nothing to drop. A type with state fails to
+//!   compile.
+//! - `std` links the standard library and leaves out the panic handler, so the algorithm
+//!   logic can run on the host, see `examples/soak.rs`. `examples/lifecycle.rs` also runs
+//!   the entry points of [`algorithm!`] on the host, under Miri with
+//!   `cargo +nightly miri run --example lifecycle --features std,verify`. [`host`]
+//!   calls those entry points like a debug probe host, see `examples/simulate.rs`.
+//!   Never enable it for a blob.
+//! - `strict-align` makes the entry points reject addresses that do not have the
+//!   `write_align` or `erase_align` of [`algorithm!`], or that are not at the start of a
+//!   `min_erase_size` block, with [`ERR_ALIGN`], and program data that is not a multiple
+//!   of `min_program_size` long with [`ERR_LENGTH`].
+//! - `strict-clock` makes `Init` reject a clock outside of the `min_clock` and
+//!   `max_clock` of [`algorithm!`] with [`ERR_CLOCK`].
+//! - `strict-geometry` checks at compile time that the `sectors` of [`algorithm!`]
+//!   cover `flash_size` exactly, so the top of the flash cannot silently be left out,
+//!   and makes `Init` reject an address outside of the flash (or the `config_region`)
+//!   and `EraseSector` an address that is not in any of the declared sectors with
+//!   [`ERR_ADDRESS`], and program data that reaches past the end of the flash with
+//!   [`ERR_OUT_OF_BOUNDS`].
+//! - `stub-unsupported` emits the optional entry points `EraseChip`, `Verify`,
+//!   `ReadFlash`, `BlankCheck`, `ProgramAndVerify`, `Abort`, `DumpConfig`,
+//!   `VerifyInPlace` and `IsRangeBlank` even when their feature is
+//!   not enabled, as stubs that only return [`ERR_UNSUPPORTED`], for hosts that call
+//!   through a table of every entry point instead of looking up the symbols. The
+//!   `Capabilities` bits still only report the real ones.
+//! - `timeout-report` exposes [`TIMEOUT_ELAPSED`] so the host can read back how long
+//!   the algorithm waited before giving up with [`ERR_TIMEOUT`].
+//! - `typed-args` makes the [`Address`] and [`Clock`] arguments of the constructors of
+//!   [`FlashAlgorithm`] newtypes instead of plain integers, so they cannot be swapped.
+//! - `vendor-status` exposes [`VENDOR_STATUS`] so the host can read back the raw
+//!   controller status register value that [`vendor_error()`] recorded with an error.
+//! - `verify-chunked` keeps a running checksum across `Verify` calls without data, see
+//!   [Chunked verification](#chunked-verification). It implies `verify-crc`.
+//! - `verify-crc` makes `Verify` without data compute the CRC-32 of the region and store
+//!   it in [`VERIFY_CRC`] instead of checking that it is erased, see
+//!   [`FlashAlgorithm::crc()`]. It implies `verify`.
+//! - `verify-diag` lets [`FlashAlgorithm::verify()`] record the offsets of several
+//!   mismatching bytes in [`VERIFY_MISMATCHES`] with [`record_mismatch()`], for the host
+//!   to report after a failed `Verify`. It implies `verify`.
+//! - `verify-in-place` adds a `VerifyInPlace(addr, data, size)` entry point that
+//!   compares the flash with a copy the host already has in target RAM, see
+//!   [`FlashAlgorithm::verify_in_place()`]. It implies `verify`.
+//! - `verify-progress` makes [`FlashAlgorithm::verify()`] report how many bytes it
+//!   compared, and `Verify` return an address as in CMSIS-Pack instead of a status, see
+//!   [Verify progress](#verify-progress). It implies `verify`.
+//!
+//! Combinations of features that cannot work together, and fields of [`algorithm!`]
+//! that would make an enabled feature do nothing, fail to compile with a message that
+//! names them.
+//!
+//! # Chunked verification
+//!
+//! With `verify-chunked` the host can checksum a region larger than it wants to verify
+//! in one call by sending it as consecutive chunks. `Verify` without data keeps the
+//! running [`Crc32`] between calls:
+//!
+//! - After `Init` there is no running checksum.
+//! - A chunk whose `addr` is the end of the previous chunk continues the running
+//!   checksum. Any other chunk starts a new one.
+//! - `Verify` stores the checksum of everything from the start of the run up to the end
+//!   of this chunk in [`VERIFY_CRC`], so after the last chunk it holds the checksum of
+//!   the whole region.
+//! - A chunk with `size` 0, one for which [`FlashAlgorithm::crc()`] fails, or one that
+//!   ends at the top of the address space ends the run, so the next chunk starts a new
+//!   checksum wherever it is.
+//!
+//! Verification with data needs no state, every chunk is compared on its own.
+//!
+//! In terms of a streaming hash: the host resets by starting at a new address or sending
+//! a chunk with `size` 0, accumulates by sending the chunks back to back, and there is no
+//! separate finalize call, `VERIFY_CRC` always holds the finished CRC-32 of the run so
+//! far. To check a whole image the host compares it after the last chunk with the
+//! CRC-32 of the image it programmed. The state is a [`Crc32`] and the next address,
+//! whatever the size of the region.
+//!
+//! # Verify progress
+//!
+//! Without `verify-progress` `Verify` returns a status, 0 when the region matches. With
+//! it, [`FlashAlgorithm::verify()`] returns `Ok(n)` with the number of bytes it
+//! compared, or `Err((offset, code))` with the offset of the first mismatch from
+//! `address` and the error. `Verify` turns that into an address the way CMSIS-Pack
+//! specifies it, so the host learns how far verification got:
+//!
+//! | `verify()` returns  | `Verify` returns   | [`VERIFY_STATUS`]          |
+//! |---------------------|--------------------|----------------------------|
+//! | `Ok(size)`          | `addr + size`      | 0                          |
+//! | `Ok(n)`, `n < size` | `addr + n`         | 0                          |
+//! | `Err((offset, e))`  | `addr + offset`    | `e`                        |
+//!
+//! So `Verify` succeeded if and only if it returned `addr + size`. Anything else is the
+//! first address that was not verified, and the host reads the `VERIFY_STATUS` symbol
+//! to tell a mismatch, with its error code, from an algorithm that stopped early, with
+//! 0. Counts and offsets larger than `size` are clamped to `size`, and the addition
+//! wraps for a region that ends at the top of the address space.
+//!
+//! `Verify` without data checks that the region is erased as before. It returns
+//! `addr + size` when it is, and `addr` otherwise, with the error in `VERIFY_STATUS`.
+//! With `verify-crc` the same goes for computing the checksum, which is in
+//! [`VERIFY_CRC`] when `Verify` returned `addr + size`.
+//!
+//! Where the shim needs a plain result, for `ProgramAndVerify`, `VerifyInPlace` and the
+//! option bytes of `config_region`, `Ok(n)` with `n < size` becomes
+//! [`ERR_VERIFY_INCOMPLETE`]. The return value of `Verify` is 32 bits wide, so the
+//! feature cannot be combined with `addr64`.
+//!
+//! # Addresses above 4 GiB
+//!
+//! With the `addr64` feature [`Addr`] is `u64` instead of `u32`. This changes the ABI
+//! in ways the host has to know about:
+//!
+//! - The `addr` argument of every entry point is a 64-bit value. It is the first
+//!   argument everywhere, so on ARM and 32-bit RISC-V it is passed in the first two
+//!   argument registers (`r0`/`a0` holding the low word) and all following arguments
+//!   move up by one register.
+//! - In the `FlashDevice` descriptor `dev_addr`, `device_size` and both fields of each
+//!   sector entry are 64-bit. `dev_addr` is aligned to 8 bytes, which inserts 4 bytes of
+//!   padding after `dev_type`, and the sector list is terminated by an entry with all
+//!   bits set. The descriptor is no longer CMSIS-Pack compatible.
+//!
+//! # Minimal descriptor
+//!
+//! The full `FlashDevice` descriptor of CMSIS-Pack, laid out as in
+//! [`DESCRIPTOR_LAYOUT`], is at least 176 bytes, most of them the name. With
+//! `minimal-descriptor` it is replaced by one without the name, device type, flash
+//! address and size, page size, erased value and timeouts:
+//!
+//! - at offset 0, the 4 bytes of [`MINIMAL_DESCRIPTOR_MAGIC`],
+//! - at 4, `vers` as a `u16`, 1 like in the full descriptor,
+//! - at 6, 2 bytes of padding that are 0,
+//! - at 8, the sector list.
+//!
+//! The sector list is the same in both layouts: `(size, address)` pairs of [`Addr`] with
+//! the address relative to the start of the flash, terminated by a pair with all bits
+//! set. [`descriptor_len()`] is the size of either.
+//!
+//! A host tells the two apart by the first four bytes of `FlashDevice`, which are
+//! [`MINIMAL_DESCRIPTOR_MAGIC`] only in the minimal one, as a full one starts with a
+//! `vers` of 1. It has to know the flash address, page size, erased value and timeouts
+//! of an algorithm with a minimal descriptor from elsewhere, for example its target
+//! description or the `geometry-note`. The other symbols in `DeviceData` do not change.
+//!
+//! # TrustZone
+//!
+//! An algorithm loaded into secure RAM of an Armv8-M core and called from the non-secure
+//! state needs entry points that return with `bxns` and clear the registers that could
+//! leak secure data. With the `cmse` feature every entry point generated by
+//! [`algorithm!`] uses the `"cmse-nonsecure-entry"` ABI, and the linker emits the
+//! `__acle_se_` symbols for their secure gateway veneers. This needs:
+//!
+//! - a nightly compiler and `#![feature(cmse_nonsecure_entry)]` in the algorithm crate,
+//! - a `thumbv8m.main-none-eabi` or `thumbv8m.main-none-eabihf` target,
+//! - a linker setup that places the `.gnu.sgstubs` veneers in non-secure callable
+//!   memory, which `memory.x` does not do, and a host that calls the veneers instead of
+//!   the functions themselves.
+//!
+//! All arguments of the entry points are passed in registers, also with `addr64`, as
+//! the ABI requires. Only use the feature if the host really drives the algorithm from
+//! the non-secure state; a secure host calls the regular entry points.
+//!
+//! # Floating point
+//!
+//! The host does not save the floating point registers of the halted application before
+//! it calls an entry point, so an algorithm that uses them corrupts the application's
+//! state if it is resumed afterwards. On a `-eabihf` target, such as
+//! `thumbv7em-none-eabihf` or `thumbv8m.main-none-eabihf`, the compiler may use them for
+//! any floating point arithmetic and also for moving data, so either:
+//!
+//! - build the algorithm for the soft-float `-eabi` target of the same core, which never
+//!   touches them, which is what the examples do, or
+//! - enable `fp-save`. Every entry point then stores `s0`-`s15` and `FPSCR` on its stack
+//!   first and loads them back last; `s16`-`s31` are already restored by every function
+//!   that uses them. This costs 68 bytes of stack and needs the FPU to be enabled in
+//!   `CPACR` when the entry point is called, as the application left it. It only
+//!   compiles for `-eabihf` targets and with `std`, where it does nothing.
+//!
+//! The DSP extension of Armv7E-M and Armv8-M adds no registers, only the `GE` flags in
+//! `APSR`, which are no different from the condition flags for the host.
+//!
+//! # Standalone
+//!
+//! With the `standalone` feature, [`algorithm!`] also emits a reset stub for a bring-up
+//! harness that runs the algorithm without a host, on Arm Cortex-M only:
+//!
+//! - `StandaloneVectors`, the initial stack pointer and reset vector of a vector table,
+//!   in its own `Standalone` output section, 128 byte aligned so `VTOR` can point at it.
+//! - `StandaloneReset`, which calls `Init(flash_address, 0, 1)`, the erase function,
+//!   then `UnInit` if that succeeded, and halts on a `bkpt` with the first non-zero
+//!   status, or 0, in `r0`.
+//! - `StandaloneStack`, [`STANDALONE_STACK_SIZE`] bytes of stack for it.
+//!
+//! The harness loads the whole ELF file at the address it was linked for, the vector
+//! table holds absolute addresses, and either boots through the vector table or sets up
+//! a stack itself and jumps to `StandaloneReset`. Nothing else is initialized: no clocks,
+//! no `.data` or `.bss` beyond what loading the file does, no interrupts, and nothing
+//! past `Init`. Erase, program and verify are left to a debugger that calls the entry
+//! points once the core has halted. The stub is not position independent and costs a
+//! kilobyte of RAM, so it is for development on new silicon only and must never be in
+//! a blob a host loads; `standalone` does not compile together with `std`.
+//!
+//! # Position independence
+//!
+//! The host may load the algorithm anywhere in RAM. The code is only correct at an
+//! address other than the one it was linked for (`ALGO_PLACEMENT_START_ADDRESS`,
+//! default 0) if it is position independent:
+//!
+//! - Build with `-C relocation-model=pic`, so code addresses its data relative to the
+//!   program counter instead of through absolute addresses.
+//! - Avoid data that contains pointers, such as `&str` or references in statics, trait
+//!   objects and panic messages or locations. Their values are fixed at link time.
+//!   The code generated by [`algorithm!`] contains none of these, so the default
+//!   abort-on-panic handler does not pull them in, but `unwrap()`, indexing and other
+//!   panicking code in the algorithm itself may.
+//!
+//! `ci/check-relocations.sh` checks this for the stm32f4 example by linking it with
+//! `--emit-relocs` and rejecting any absolute relocation in `PrgCode`.
 
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![no_main]
 #![macro_use]
 
-#[cfg(all(not(test), feature = "panic-handler"))]
+mod buf;
+#[cfg(feature = "counters")]
+mod counters;
+mod crc;
+mod erased;
+#[cfg(feature = "fp-save")]
+mod fp;
+#[cfg(feature = "descriptor-header")]
+mod header;
+#[cfg(feature = "std")]
+pub mod host;
+#[cfg(feature = "feature-manifest")]
+mod manifest;
+pub mod mmio;
+#[cfg(feature = "geometry-note")]
+mod note;
+mod pages;
+mod ring;
+#[cfg(feature = "rtt-minimal")]
+mod rtt;
+#[doc(hidden)]
+pub mod shim;
+mod sparse;
+#[cfg(feature = "stack-guard")]
+mod stack;
+mod stack_vec;
+#[cfg(feature = "standalone")]
+mod standalone;
+mod words;
+
+pub use buf::{check_program_len, copy_to_aligned, fill_aligned};
+#[cfg(feature = "counters")]
+pub use counters::{count_retry, Counters, COUNTERS};
+pub use crc::Crc32;
+pub use erased::first_not_erased;
+#[cfg(feature = "verify")]
+pub use erased::verify_erased_mapped;
+#[cfg(feature = "fp-save")]
+#[doc(hidden)]
+pub use fp::FpState;
+#[cfg(all(feature = "descriptor-header", feature = "std"))]
+pub use header::HeaderError;
+#[cfg(feature = "descriptor-header")]
+pub use header::{DescriptorHeader, DESCRIPTOR_MAGIC};
+#[cfg(feature = "feature-manifest")]
+pub use manifest::FEATURE_MANIFEST;
+#[cfg(feature = "geometry-note")]
+#[doc(hidden)]
+pub use note::{note_len, Note};
+pub use pages::{pages, Pages};
+pub use ring::RingBuffer;
+#[cfg(feature = "rtt-minimal")]
+pub use rtt::{hexdump, log_bytes};
+#[cfg(feature = "init-args")]
+pub use shim::{init_address, init_clock};
+pub use sparse::verify_sparse;
+#[cfg(feature = "stack-guard")]
+pub use stack::check_stack;
+pub use stack_vec::StackVec;
+#[cfg(feature = "standalone")]
+pub use standalone::STANDALONE_STACK_SIZE;
+#[cfg(feature = "standalone")]
+#[doc(hidden)]
+pub use standalone::{halt, StandaloneStack, StandaloneVectors};
+pub use words::{Word, Words};
+
+// `Cargo.toml` already enables `verify` with the features below, these keep the two in
+// sync when the list changes.
+#[cfg(all(
+    any(
+        feature = "program-verify",
+        feature = "verify-crc",
+        feature = "verify-diag",
+        feature = "verify-in-place",
+        feature = "verify-progress"
+    ),
+    not(feature = "verify")
+))]
+compile_error!("the `verify-*` and `program-verify` features require the `verify` feature");
+#[cfg(all(feature = "verify-chunked", not(feature = "verify-crc")))]
+compile_error!("`verify-chunked` requires the `verify-crc` feature");
+
+#[cfg(all(feature = "verify-progress", feature = "addr64"))]
+compile_error!("`verify-progress` returns addresses from `Verify`, which cannot hold 64 bits");
+#[cfg(all(feature = "cmse", feature = "std"))]
+compile_error!("`cmse` entry points only exist on Armv8-M targets, not with `std`");
+
+#[cfg(all(not(test), not(feature = "std"), feature = "panic-handler"))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     #[cfg(not(any(target_arch = "arm", target_arch = "riscv32")))]
     compile_error!("Panic handler can only be compiled for arm and riscv32");
 
+    trap()
+}
+
+/// Stop with an undefined instruction, which the host sees as a fault of the algorithm.
+///
+/// This is what the default panic handler does.
+pub fn trap() -> ! {
+    #[cfg(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64"))]
     unsafe {
         #[cfg(target_arch = "arm")]
         core::arch::asm!("udf #0");
@@ -25,14 +413,382 @@ fn panic(_info: &core::panic::PanicInfo) -> ! {
         core::arch::asm!("UNIMP");
         core::hint::unreachable_unchecked();
     }
+    #[cfg(not(any(target_arch = "arm", target_arch = "riscv32", target_arch = "riscv64")))]
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Use `handler`, a `fn(&core::panic::PanicInfo)`, as the panic handler.
+///
+/// The handler can do whatever it needs to report the panic, for example log it over
+/// RTT, and may return: the algorithm then stops with [`trap()`] like it does with the
+/// default handler. This needs the default `panic-handler` feature to be disabled,
+/// otherwise there would be two panic handlers.
+///
+/// ```ignore
+/// fn report(info: &core::panic::PanicInfo) {
+///     flash_algorithm::log_bytes(b"panic\n");
+/// }
+///
+/// flash_algorithm::panic_handler!(report);
+/// ```
+#[cfg(not(feature = "panic-handler"))]
+#[macro_export]
+macro_rules! panic_handler {
+    ($handler:path) => {
+        #[panic_handler]
+        fn _flash_algorithm_panic(info: &core::panic::PanicInfo) -> ! {
+            let handler: fn(&core::panic::PanicInfo) = $handler;
+            handler(info);
+            $crate::trap()
+        }
+    };
+}
+/// Use `handler`, a `fn(&core::panic::PanicInfo)`, as the panic handler.
+///
+/// This needs the default `panic-handler` feature to be disabled.
+#[cfg(feature = "panic-handler")]
+#[macro_export]
+macro_rules! panic_handler {
+    ($handler:path) => {
+        core::compile_error!(
+            "`panic_handler!` requires disabling the default `panic-handler` feature"
+        );
+    };
 }
 
 pub const FUNCTION_ERASE: u32 = 1;
 pub const FUNCTION_PROGRAM: u32 = 2;
 pub const FUNCTION_VERIFY: u32 = 3;
+#[cfg(feature = "blank-check")]
+pub const FUNCTION_BLANK: u32 = 4;
+
+/// `EraseChip` is available.
+pub const CAPABILITY_ERASE_CHIP: u32 = 1 << 0;
+/// `Verify` is available.
+pub const CAPABILITY_VERIFY: u32 = 1 << 1;
+/// `ReadFlash` is available.
+pub const CAPABILITY_READ_FLASH: u32 = 1 << 2;
+/// `BlankCheck` is available.
+pub const CAPABILITY_BLANK_CHECK: u32 = 1 << 3;
+/// `ProgramAndVerify` is available.
+pub const CAPABILITY_PROGRAM_VERIFY: u32 = 1 << 4;
+/// `Verify` without data stores a CRC-32 in `VERIFY_CRC` instead of checking the region.
+pub const CAPABILITY_VERIFY_CRC: u32 = 1 << 5;
+/// `Abort` is available.
+pub const CAPABILITY_ABORT: u32 = 1 << 6;
+/// `Verify` without data continues the checksum of the previous chunk.
+pub const CAPABILITY_VERIFY_CHUNKED: u32 = 1 << 7;
+/// `DumpConfig` is available.
+pub const CAPABILITY_DUMP_CONFIG: u32 = 1 << 8;
+/// `VerifyInPlace` is available.
+pub const CAPABILITY_VERIFY_IN_PLACE: u32 = 1 << 9;
+/// `IsRangeBlank` is available.
+pub const CAPABILITY_IS_RANGE_BLANK: u32 = 1 << 10;
+
+/// The optional operations compiled into the algorithm, as returned by the `Capabilities`
+/// entry point.
+pub const CAPABILITIES: u32 = {
+    let mut capabilities = 0;
+    if cfg!(feature = "erase-chip") {
+        capabilities |= CAPABILITY_ERASE_CHIP;
+    }
+    if cfg!(feature = "verify") {
+        capabilities |= CAPABILITY_VERIFY;
+    }
+    if cfg!(feature = "read-flash") {
+        capabilities |= CAPABILITY_READ_FLASH;
+    }
+    if cfg!(feature = "blank-check") {
+        capabilities |= CAPABILITY_BLANK_CHECK;
+    }
+    if cfg!(feature = "program-verify") {
+        capabilities |= CAPABILITY_PROGRAM_VERIFY;
+    }
+    if cfg!(feature = "verify-crc") {
+        capabilities |= CAPABILITY_VERIFY_CRC;
+    }
+    if cfg!(feature = "abort") {
+        capabilities |= CAPABILITY_ABORT;
+    }
+    if cfg!(feature = "verify-chunked") {
+        capabilities |= CAPABILITY_VERIFY_CHUNKED;
+    }
+    if cfg!(feature = "debug-dump") {
+        capabilities |= CAPABILITY_DUMP_CONFIG;
+    }
+    if cfg!(feature = "verify-in-place") {
+        capabilities |= CAPABILITY_VERIFY_IN_PLACE;
+    }
+    if cfg!(feature = "blank-query") {
+        capabilities |= CAPABILITY_IS_RANGE_BLANK;
+    }
+    capabilities
+};
 
 pub type ErrorCode = core::num::NonZeroU32;
 
+/// The type of flash addresses, in the trait methods, entry points and descriptor.
+#[cfg(not(feature = "addr64"))]
+pub type Addr = u32;
+/// The type of flash addresses, in the trait methods, entry points and descriptor.
+#[cfg(feature = "addr64")]
+pub type Addr = u64;
+
+/// The type of the `address` argument of the constructors of [`FlashAlgorithm`].
+///
+/// A plain [`Addr`], unless the `typed-args` feature makes it a newtype that cannot be
+/// mixed up with a [`Clock`].
+#[cfg(not(feature = "typed-args"))]
+pub type Address = Addr;
+/// The `address` argument of the constructors of [`FlashAlgorithm`], the start address
+/// of the flash region `Init` was called for.
+#[cfg(feature = "typed-args")]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address(pub Addr);
+
+/// The type of the `clock` argument of the constructors of [`FlashAlgorithm`].
+///
+/// A plain `u32`, unless the `typed-args` feature makes it a newtype that cannot be
+/// mixed up with an [`Address`].
+#[cfg(not(feature = "typed-args"))]
+pub type Clock = u32;
+/// The `clock` argument of the constructors of [`FlashAlgorithm`], in Hertz.
+#[cfg(feature = "typed-args")]
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Clock(pub u32);
+
+#[cfg(feature = "typed-args")]
+impl From<Addr> for Address {
+    fn from(address: Addr) -> Self {
+        Self(address)
+    }
+}
+
+#[cfg(feature = "typed-args")]
+impl From<Address> for Addr {
+    fn from(address: Address) -> Self {
+        address.0
+    }
+}
+
+#[cfg(feature = "typed-args")]
+impl From<u32> for Clock {
+    fn from(clock: u32) -> Self {
+        Self(clock)
+    }
+}
+
+#[cfg(feature = "typed-args")]
+impl From<Clock> for u32 {
+    fn from(clock: Clock) -> Self {
+        clock.0
+    }
+}
+
+/// The operation did not complete within the time the algorithm allows for it.
+pub const ERR_TIMEOUT: ErrorCode = error_code(2);
+
+/// The stack grew past its bottom, see [`check_stack()`].
+#[cfg(feature = "stack-guard")]
+pub const ERR_STACK_OVERFLOW: ErrorCode = error_code(3);
+
+/// `Init` was called with a clock outside of `min_clock..=max_clock`.
+#[cfg(feature = "strict-clock")]
+pub const ERR_CLOCK: ErrorCode = error_code(4);
+
+/// `Init` was called with an address outside of the declared flash, or `EraseSector`
+/// with one that is not in any declared sector. The latter is also checked by
+/// `sector-size`, which needs the sector to pass on its size.
+///
+/// The defaults that read the flash through the memory map return it for a region the
+/// core cannot address, like one above 4 GiB with `addr64` on a 32-bit core.
+pub const ERR_ADDRESS: ErrorCode = error_code(5);
+
+/// An operation started at an address that is not a multiple of `write_align` or
+/// `min_erase_size`, or a buffer passed to [`copy_to_aligned()`] or [`fill_aligned()`] is
+/// not aligned.
+pub const ERR_ALIGN: ErrorCode = error_code(6);
+
+/// Program data is not a multiple of `min_program_size` long, see
+/// [`check_program_len()`], a buffer passed to [`copy_to_aligned()`] or
+/// [`fill_aligned()`] is too short, or a [`StackVec`] is full.
+pub const ERR_LENGTH: ErrorCode = error_code(7);
+
+/// Returned by the stub of an optional entry point whose feature was not enabled, see
+/// the `stub-unsupported` feature.
+#[cfg(feature = "stub-unsupported")]
+pub const ERR_UNSUPPORTED: ErrorCode = error_code(8);
+
+/// A region that [`verify_erased_mapped()`] checked holds a byte that is not the erased
+/// value.
+#[cfg(feature = "verify")]
+pub const ERR_NOT_ERASED: ErrorCode = error_code(9);
+
+/// `Init` found a different hash than that of its `device_name` in `ExpectedNameHash`,
+/// see the `name-guard` feature.
+#[cfg(feature = "name-guard")]
+pub const ERR_NAME: ErrorCode = error_code(10);
+
+/// An operation would reach past the end of the flash, `flash_address + flash_size`.
+///
+/// With `strict-geometry`, `ProgramPage` and `ProgramAndVerify` return it for data that
+/// does not fit. `EraseSector` cannot reach past the end then, as the sectors have to
+/// cover the flash exactly. Algorithms can return it themselves without the feature.
+pub const ERR_OUT_OF_BOUNDS: ErrorCode = error_code(11);
+
+/// [`FlashAlgorithm::verify()`] compared fewer bytes than it was asked to, see
+/// [Verify progress](crate#verify-progress).
+#[cfg(feature = "verify-progress")]
+pub const ERR_VERIFY_INCOMPLETE: ErrorCode = error_code(12);
+
+const fn error_code(code: u32) -> ErrorCode {
+    match ErrorCode::new(code) {
+        Some(code) => code,
+        None => panic!("error codes must be non-zero"),
+    }
+}
+
+/// Call `predicate` until it returns `true`, at most `max_iters` times.
+///
+/// Returns [`ERR_TIMEOUT`] if the predicate never became `true`. There is no notion of
+/// time here, so `max_iters` has to be chosen from the cost of one call and the clock
+/// speed. With the `timeout-report` feature the number of iterations is recorded in
+/// [`TIMEOUT_ELAPSED`] on timeout.
+///
+/// ```ignore
+/// poll_until(|| unsafe { read_volatile(FLASH_SR) } & SR_BSY == 0, 1_000_000)?;
+/// ```
+pub fn poll_until<F: FnMut() -> bool>(mut predicate: F, max_iters: u32) -> Result<(), ErrorCode> {
+    for _ in 0..max_iters {
+        if predicate() {
+            return Ok(());
+        }
+    }
+    #[cfg(feature = "timeout-report")]
+    return Err(timeout(max_iters));
+    #[cfg(not(feature = "timeout-report"))]
+    {
+        #[cfg(feature = "counters")]
+        counters::increment(&COUNTERS.timeouts);
+        Err(ERR_TIMEOUT)
+    }
+}
+
+/// How long the last operation that timed out waited, in whatever unit the algorithm
+/// measures (polling iterations, milliseconds, ...).
+///
+/// The host can read the `TIMEOUT_ELAPSED` symbol after an entry point returned
+/// [`ERR_TIMEOUT`]. It is only written by [`timeout()`], so the host should clear it
+/// before starting an operation if it wants to tell stale values apart.
+#[cfg(feature = "timeout-report")]
+#[no_mangle]
+pub static TIMEOUT_ELAPSED: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// Record `elapsed` in [`TIMEOUT_ELAPSED`] and return [`ERR_TIMEOUT`].
+///
+/// ```ignore
+/// return Err(flash_algorithm::timeout(iterations));
+/// ```
+#[cfg(feature = "timeout-report")]
+pub fn timeout(elapsed: u32) -> ErrorCode {
+    TIMEOUT_ELAPSED.store(elapsed, core::sync::atomic::Ordering::Relaxed);
+    #[cfg(feature = "counters")]
+    counters::increment(&COUNTERS.timeouts);
+    ERR_TIMEOUT
+}
+
+/// The raw value of a flash controller status register, recorded by [`vendor_error()`]
+/// together with an error code.
+///
+/// The host can read the `VENDOR_STATUS` symbol after an entry point returned an error
+/// to show the exact controller flags. It is only written by [`vendor_error()`], so the
+/// host should clear it before a call it wants to diagnose; 0 then means the algorithm
+/// did not record a status for the error.
+#[cfg(feature = "vendor-status")]
+#[no_mangle]
+pub static VENDOR_STATUS: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// Record `status` in [`VENDOR_STATUS`] and return `code`.
+///
+/// The recommended convention is to read the status register once, before clearing any
+/// of its flags, and pass the whole value, not only the error bits. `code` should still
+/// say what went wrong in generic terms, for hosts that do not look at the status.
+///
+/// ```ignore
+/// let status = read_volatile(FLASH_SR);
+/// if status & SR_ERRORS != 0 {
+///     return Err(flash_algorithm::vendor_error(ERR_CONTROLLER, status));
+/// }
+/// ```
+#[cfg(feature = "vendor-status")]
+pub fn vendor_error(code: ErrorCode, status: u32) -> ErrorCode {
+    VENDOR_STATUS.store(status, core::sync::atomic::Ordering::Relaxed);
+    code
+}
+
+/// The error code of the last mismatch `Verify` returned the address of, 0 if it did not
+/// find one, see [Verify progress](crate#verify-progress).
+///
+/// `Verify` resets it before it calls into the algorithm, so the host can read it after
+/// every call that did not return `addr + size`.
+#[cfg(feature = "verify-progress")]
+#[no_mangle]
+pub static VERIFY_STATUS: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// The CRC-32 computed by the last `Verify` without data, see [`FlashAlgorithm::crc()`].
+///
+/// `Verify` resets it to 0 before it calls into the algorithm and only stores the
+/// checksum if that succeeded, so the host reads it after a `Verify` that did.
+#[cfg(feature = "verify-crc")]
+#[no_mangle]
+pub static VERIFY_CRC: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// How many mismatches [`VERIFY_MISMATCHES`] can hold.
+#[cfg(feature = "verify-diag")]
+pub const VERIFY_MISMATCH_CAPACITY: usize = 16;
+
+/// The offsets, relative to the `addr` passed to `Verify`, of the first mismatching bytes
+/// found by the last verification.
+///
+/// Only the first `min(VERIFY_MISMATCH_COUNT, VERIFY_MISMATCH_CAPACITY)` entries are
+/// valid. Both are reset by `Verify` and `ProgramAndVerify` before they call into the
+/// algorithm, so the host can read them after either one returned.
+#[cfg(feature = "verify-diag")]
+#[no_mangle]
+pub static VERIFY_MISMATCHES: [core::sync::atomic::AtomicU32; VERIFY_MISMATCH_CAPACITY] =
+    [const { core::sync::atomic::AtomicU32::new(0) }; VERIFY_MISMATCH_CAPACITY];
+
+/// How many mismatches were passed to [`record_mismatch()`] since the last reset. This
+/// keeps counting when [`VERIFY_MISMATCHES`] is full.
+#[cfg(feature = "verify-diag")]
+#[no_mangle]
+pub static VERIFY_MISMATCH_COUNT: core::sync::atomic::AtomicU32 =
+    core::sync::atomic::AtomicU32::new(0);
+
+/// Record a mismatching byte at `offset` from the start of the region being verified.
+///
+/// [`FlashAlgorithm::verify()`] should call this for every mismatch it finds and still
+/// return an error as usual; the host only looks at the recorded offsets when it does.
+///
+/// ```ignore
+/// if actual != expected {
+///     flash_algorithm::record_mismatch(offset);
+/// }
+/// ```
+#[cfg(feature = "verify-diag")]
+pub fn record_mismatch(offset: u32) {
+    use core::sync::atomic::Ordering::Relaxed;
+    let count = VERIFY_MISMATCH_COUNT.load(Relaxed);
+    if let Some(slot) = VERIFY_MISMATCHES.get(count as usize) {
+        slot.store(offset, Relaxed);
+    }
+    VERIFY_MISMATCH_COUNT.store(count.saturating_add(1), Relaxed);
+}
+
 pub trait FlashAlgorithm: Sized + 'static {
     /// Initialize the flash algorithm.
     ///
@@ -40,12 +796,122 @@ pub trait FlashAlgorithm: Sized + 'static {
     /// for the function to be executed or no initialization at all. It is up to the implementor
     /// to decide this.
     ///
+    /// `Init` reaches this through [`FlashAlgorithm::new_erase()`],
+    /// [`FlashAlgorithm::new_program()`] and [`FlashAlgorithm::new_verify()`] unless they
+    /// are overridden, and calls it directly for any other function.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash region to program.
+    /// * `clock` - The clock speed in Hertz for programming the device.
+    /// * `function` - The function for which this initialization is for.
+    #[cfg(not(feature = "scratch-buffer"))]
+    fn new(address: Address, clock: Clock, function: Function) -> Result<Self, ErrorCode>;
+
+    /// Initialize the flash algorithm.
+    ///
+    /// It can happen that the flash algorithm does not need any specific initialization
+    /// for the function to be executed or no initialization at all. It is up to the implementor
+    /// to decide this.
+    ///
+    /// `Init` reaches this through [`FlashAlgorithm::new_erase()`],
+    /// [`FlashAlgorithm::new_program()`] and [`FlashAlgorithm::new_verify()`] unless they
+    /// are overridden, and calls it directly for any other function.
+    ///
     /// # Arguments
     ///
     /// * `address` - The start address of the flash region to program.
     /// * `clock` - The clock speed in Hertz for programming the device.
     /// * `function` - The function for which this initialization is for.
-    fn new(address: u32, clock: u32, function: Function) -> Result<Self, ErrorCode>;
+    /// * `scratch` - A zero-initialized buffer of `scratch_size` bytes, as declared in
+    ///   [`algorithm!`]. It stays valid until the instance is dropped by `UnInit`.
+    #[cfg(feature = "scratch-buffer")]
+    fn new(
+        address: Address,
+        clock: Clock,
+        function: Function,
+        scratch: &'static mut [u8],
+    ) -> Result<Self, ErrorCode>;
+
+    /// Initialize the flash algorithm for [`Function::Erase`].
+    ///
+    /// `Init` calls this instead of [`FlashAlgorithm::new()`] for erasing. Override it to
+    /// handle only that function, the default calls `new`.
+    #[cfg(not(feature = "scratch-buffer"))]
+    fn new_erase(address: Address, clock: Clock) -> Result<Self, ErrorCode> {
+        Self::new(address, clock, Function::Erase)
+    }
+
+    /// Initialize the flash algorithm for [`Function::Program`].
+    ///
+    /// `Init` calls this instead of [`FlashAlgorithm::new()`] for programming. Override it
+    /// to handle only that function, the default calls `new`.
+    #[cfg(not(feature = "scratch-buffer"))]
+    fn new_program(address: Address, clock: Clock) -> Result<Self, ErrorCode> {
+        Self::new(address, clock, Function::Program)
+    }
+
+    /// Initialize the flash algorithm for [`Function::Verify`].
+    ///
+    /// `Init` calls this instead of [`FlashAlgorithm::new()`] for verifying. Override it to
+    /// handle only that function, the default calls `new`.
+    #[cfg(not(feature = "scratch-buffer"))]
+    fn new_verify(address: Address, clock: Clock) -> Result<Self, ErrorCode> {
+        Self::new(address, clock, Function::Verify)
+    }
+
+    /// Initialize the flash algorithm for [`Function::Erase`].
+    ///
+    /// `Init` calls this instead of [`FlashAlgorithm::new()`] for erasing. Override it to
+    /// handle only that function, the default calls `new`.
+    #[cfg(feature = "scratch-buffer")]
+    fn new_erase(
+        address: Address,
+        clock: Clock,
+        scratch: &'static mut [u8],
+    ) -> Result<Self, ErrorCode> {
+        Self::new(address, clock, Function::Erase, scratch)
+    }
+
+    /// Initialize the flash algorithm for [`Function::Program`].
+    ///
+    /// `Init` calls this instead of [`FlashAlgorithm::new()`] for programming. Override it
+    /// to handle only that function, the default calls `new`.
+    #[cfg(feature = "scratch-buffer")]
+    fn new_program(
+        address: Address,
+        clock: Clock,
+        scratch: &'static mut [u8],
+    ) -> Result<Self, ErrorCode> {
+        Self::new(address, clock, Function::Program, scratch)
+    }
+
+    /// Initialize the flash algorithm for [`Function::Verify`].
+    ///
+    /// `Init` calls this instead of [`FlashAlgorithm::new()`] for verifying. Override it to
+    /// handle only that function, the default calls `new`.
+    #[cfg(feature = "scratch-buffer")]
+    fn new_verify(
+        address: Address,
+        clock: Clock,
+        scratch: &'static mut [u8],
+    ) -> Result<Self, ErrorCode> {
+        Self::new(address, clock, Function::Verify, scratch)
+    }
+
+    /// Check that the flash controller responds, for example by reading a register with
+    /// a known value or checking that its clock is running.
+    ///
+    /// `Init` calls this right after the instance was created by
+    /// [`FlashAlgorithm::new()`] or one of the typed constructors, before it is stored.
+    /// If it fails, the instance is dropped and `Init` returns the error, so the algorithm
+    /// is left uninitialized just as if `new` had failed and the host sees the problem
+    /// before the first operation instead of a hang in the middle of one. The default
+    /// does nothing.
+    #[cfg(feature = "self-test")]
+    fn self_test(&mut self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
 
     /// Erase entire chip. Will only be called after [`FlashAlgorithm::new()`] with [`Function::Erase`].
     #[cfg(feature = "erase-chip")]
@@ -56,225 +922,1828 @@ pub trait FlashAlgorithm: Sized + 'static {
     /// # Arguments
     ///
     /// * `address` - The start address of the flash sector to erase.
-    fn erase_sector(&mut self, address: u32) -> Result<(), ErrorCode>;
+    #[cfg(not(feature = "sector-size"))]
+    fn erase_sector(&mut self, address: Addr) -> Result<(), ErrorCode>;
+
+    /// Erase sector. Will only be called after [`FlashAlgorithm::new()`] with [`Function::Erase`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash sector to erase.
+    /// * `size` - The size of that sector according to the `sectors` declared in [`algorithm!`].
+    #[cfg(feature = "sector-size")]
+    fn erase_sector(&mut self, address: Addr, size: Addr) -> Result<(), ErrorCode>;
+
+    /// Program bytes. Will only be called after [`FlashAlgorithm::new()`] with [`Function::Program`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash page to program.
+    /// * `data` - The data to be written to the page.
+    #[cfg(not(feature = "page-index"))]
+    fn program_page(&mut self, address: Addr, data: &[u8]) -> Result<(), ErrorCode>;
 
     /// Program bytes. Will only be called after [`FlashAlgorithm::new()`] with [`Function::Program`].
     ///
     /// # Arguments
     ///
     /// * `address` - The start address of the flash page to program.
+    /// * `page` - The index of the page within the flash, `(address - flash_address) / page_size`.
     /// * `data` - The data to be written to the page.
-    fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode>;
+    #[cfg(feature = "page-index")]
+    fn program_page(&mut self, address: Addr, page: u32, data: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Erase the option byte region. Called instead of [`FlashAlgorithm::erase_sector()`]
+    /// for addresses within the `config_region` declared in [`algorithm!`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the option bytes to erase.
+    #[cfg(feature = "config-region")]
+    fn erase_option(&mut self, address: Addr) -> Result<(), ErrorCode>;
+
+    /// Program option bytes. Called instead of [`FlashAlgorithm::program_page()`]
+    /// for addresses within the `config_region` declared in [`algorithm!`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the option bytes to program.
+    /// * `data` - The data to be written.
+    #[cfg(feature = "config-region")]
+    fn program_option(&mut self, address: Addr, data: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Verify the firmware that has been programmed.  Will only be called after [`FlashAlgorithm::new()`] with [`Function::Verify`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash to verify.
+    /// * `size` - The length of the data to verify.
+    /// * `data` - The data to compare with. `Verify` without data calls
+    ///   [`FlashAlgorithm::verify_erased()`] instead, whose default calls this with `None`.
+    #[cfg(all(feature = "verify", not(feature = "verify-progress")))]
+    fn verify(&mut self, address: Addr, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode>;
 
     /// Verify the firmware that has been programmed.  Will only be called after [`FlashAlgorithm::new()`] with [`Function::Verify`].
     ///
+    /// Returns the number of bytes compared, normally `size`, or the offset of the first
+    /// mismatch from `address` together with the error. See
+    /// [Verify progress](crate#verify-progress) for what `Verify` makes of it.
+    ///
     /// # Arguments
     ///
     /// * `address` - The start address of the flash to verify.
     /// * `size` - The length of the data to verify.
-    /// * `data` - The data to compare with.
+    /// * `data` - The data to compare with. `Verify` without data calls
+    ///   [`FlashAlgorithm::verify_erased()`] instead, whose default calls this with `None`.
+    #[cfg(feature = "verify-progress")]
+    fn verify(
+        &mut self,
+        address: Addr,
+        size: u32,
+        data: Option<&[u8]>,
+    ) -> Result<u32, (u32, ErrorCode)>;
+
+    /// Check that a region is erased. Will only be called after [`FlashAlgorithm::new()`]
+    /// with [`Function::Verify`].
+    ///
+    /// `Verify` calls this when the host passes no data, unless `verify-crc` makes it
+    /// compute a checksum instead, once per run of sectors with its own `erased` value.
+    /// The default calls [`FlashAlgorithm::verify()`] without data, which ignores
+    /// `erased`. Algorithms for memory mapped flash with runs of different erased values
+    /// can override it with [`verify_erased_mapped()`], which reads the region through
+    /// the memory map.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash to check.
+    /// * `size` - The length of the region to check.
+    /// * `erased` - The value of an erased byte, the `empty_value` declared in
+    ///   [`algorithm!`] or the `erased` value of the run of sectors the region is in.
     #[cfg(feature = "verify")]
-    fn verify(&mut self, address: u32, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode>;
+    fn verify_erased(&mut self, address: Addr, size: u32, erased: u8) -> Result<(), ErrorCode> {
+        let _ = erased;
+        shim::verify_exact(self, address, size, None)
+    }
+
+    /// Compare the flash with a copy of the data that is already in target RAM. Called
+    /// by the `VerifyInPlace` entry point, only after [`FlashAlgorithm::new()`] with
+    /// [`Function::Verify`].
+    ///
+    /// `VerifyInPlace(addr, data, size)` takes the flash address first and the address of
+    /// the copy in target RAM second, for a host that staged the image in RAM to program
+    /// it and does not want to transfer it again. The copy must be readable by the core,
+    /// must not overlap the flash region, the algorithm or its stack, and has to stay
+    /// unchanged until the entry point returns. A null `data` or a copy that wraps around
+    /// the end of the address space is rejected with [`ERR_OUT_OF_BOUNDS`] before this is
+    /// called, and with `strict-geometry` so is a flash region that does not fit in the
+    /// flash. The default calls [`FlashAlgorithm::verify()`] with `data`.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash to verify.
+    /// * `data` - The copy in target RAM, as long as the region to verify.
+    #[cfg(feature = "verify-in-place")]
+    fn verify_in_place(&mut self, address: Addr, data: &[u8]) -> Result<(), ErrorCode> {
+        shim::verify_exact(self, address, data.len() as u32, Some(data))
+    }
+
+    /// Whether a failed [`FlashAlgorithm::program_page()`] should be tried again, because
+    /// `error` is one the flash sometimes reports spuriously.
+    ///
+    /// `ProgramPage` retries up to `program_retries` times, see [`algorithm!`]. The
+    /// default retries nothing.
+    ///
+    /// ```ignore
+    /// fn is_transient(&self, error: ErrorCode) -> bool {
+    ///     error == ERR_PROGRAM_VERIFY
+    /// }
+    /// ```
+    #[cfg(feature = "retry")]
+    fn is_transient(&self, error: ErrorCode) -> bool {
+        let _ = error;
+        false
+    }
+
+    /// Check that a flash region is erased. Will only be called after [`FlashAlgorithm::new()`] with [`Function::Blank`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash to check.
+    /// * `size` - The length of the region to check.
+    /// * `pattern` - The value of an erased byte.
+    #[cfg(feature = "blank-check")]
+    fn blank_check(&mut self, address: Addr, size: u32, pattern: u8) -> Result<(), ErrorCode>;
+
+    /// Whether a flash region is erased. Called by the `IsRangeBlank` entry point, after
+    /// [`FlashAlgorithm::new()`] with any [`Function`].
+    ///
+    /// Unlike [`FlashAlgorithm::blank_check()`], a region that is not erased is not an
+    /// error but `Ok(false)`, and `Err` is left for the check itself failing. The host
+    /// asks before it erases a sector, to skip the erase when the sector is already
+    /// blank, so it needs no separate `Init` with [`Function::Blank`] for it.
+    ///
+    /// `IsRangeBlank(addr, size, blank)` writes 1 for a blank region and 0 otherwise to
+    /// the `u32` at `blank` in target RAM and returns a status code as usual, leaving
+    /// `blank` alone on an error. A null `blank` is rejected with [`ERR_OUT_OF_BOUNDS`],
+    /// and with `strict-geometry` so is a region that does not fit in the flash. A
+    /// region that spans runs of sectors with different `erased` values is checked one
+    /// run at a time, like by [`FlashAlgorithm::verify_erased()`], stopping at the first
+    /// run that is not blank.
+    ///
+    /// The default reads the region through the memory map, see
+    /// [`first_not_erased()`], and returns [`ERR_ADDRESS`] for a region the core cannot
+    /// address. Algorithms whose controller has a blank check command
+    /// should use it instead, it is usually much faster than reading every byte.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash to check.
+    /// * `size` - The length of the region to check.
+    /// * `erased` - The value of an erased byte, the `empty_value` declared in
+    ///   [`algorithm!`] or the `erased` value of the run of sectors the region is in.
+    #[cfg(feature = "blank-query")]
+    fn is_range_blank(&mut self, address: Addr, size: u32, erased: u8) -> Result<bool, ErrorCode> {
+        let region = unsafe { erased::mapped(address, size)? };
+        Ok(first_not_erased(region, erased).is_none())
+    }
+
+    /// Compute the [`Crc32`] of a flash region so the host can compare it with the
+    /// checksum of the image instead of sending the data.
+    /// Will only be called after [`FlashAlgorithm::new()`] with [`Function::Verify`].
+    ///
+    /// `Verify` calls this when the host passes no data, stores the checksum in
+    /// [`VERIFY_CRC`] and returns the usual status, so an error is never mistaken for a
+    /// checksum.
+    ///
+    /// The default implementation reads the region through the memory map, and returns
+    /// [`ERR_ADDRESS`] for a region the core cannot address. Algorithms for flash that
+    /// is not memory mapped have to override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash to checksum.
+    /// * `size` - The length of the region to checksum.
+    #[cfg(all(feature = "verify-crc", not(feature = "verify-chunked")))]
+    fn crc(&mut self, address: Addr, size: u32) -> Result<u32, ErrorCode> {
+        let region = unsafe { erased::mapped(address, size)? };
+        let mut crc = Crc32::new();
+        for byte in region {
+            crc.update_byte(unsafe { core::ptr::read_volatile(byte) });
+        }
+        Ok(crc.finish())
+    }
+
+    /// Feed a flash region into a [`Crc32`] so the host can compare it with the checksum
+    /// of the image instead of sending the data, continuing the checksum of the previous
+    /// chunk. Will only be called after [`FlashAlgorithm::new()`] with [`Function::Verify`].
+    ///
+    /// See [Chunked verification](crate#chunked-verification) for how `Verify` calls
+    /// this. An error is returned as the status of `Verify`, with the checksum left out
+    /// of [`VERIFY_CRC`].
+    ///
+    /// The default implementation reads the region through the memory map, and returns
+    /// [`ERR_ADDRESS`] for a region the core cannot address. Algorithms for flash that
+    /// is not memory mapped have to override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `crc` - The checksum to update.
+    /// * `address` - The start address of the flash to checksum.
+    /// * `size` - The length of the region to checksum.
+    #[cfg(feature = "verify-chunked")]
+    fn crc(&mut self, crc: &mut Crc32, address: Addr, size: u32) -> Result<(), ErrorCode> {
+        let region = unsafe { erased::mapped(address, size)? };
+        for byte in region {
+            crc.update_byte(unsafe { core::ptr::read_volatile(byte) });
+        }
+        Ok(())
+    }
+
+    /// Program bytes and verify them right away, saving the host a round trip.
+    /// Will only be called after [`FlashAlgorithm::new()`] with [`Function::Program`].
+    ///
+    /// The default implementation calls [`FlashAlgorithm::program_page()`] followed by
+    /// [`FlashAlgorithm::verify()`] and returns the first error.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash page to program.
+    /// * `data` - The data to be written to the page.
+    #[cfg(all(feature = "program-verify", not(feature = "page-index")))]
+    fn program_and_verify(&mut self, address: Addr, data: &[u8]) -> Result<(), ErrorCode> {
+        self.program_page(address, data)?;
+        shim::verify_exact(self, address, data.len() as u32, Some(data))
+    }
+
+    /// Program bytes and verify them right away, saving the host a round trip.
+    /// Will only be called after [`FlashAlgorithm::new()`] with [`Function::Program`].
+    ///
+    /// The default implementation calls [`FlashAlgorithm::program_page()`] followed by
+    /// [`FlashAlgorithm::verify()`] and returns the first error.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash page to program.
+    /// * `page` - The index of the page within the flash, `(address - flash_address) / page_size`.
+    /// * `data` - The data to be written to the page.
+    #[cfg(all(feature = "program-verify", feature = "page-index"))]
+    fn program_and_verify(
+        &mut self,
+        address: Addr,
+        page: u32,
+        data: &[u8],
+    ) -> Result<(), ErrorCode> {
+        self.program_page(address, page, data)?;
+        shim::verify_exact(self, address, data.len() as u32, Some(data))
+    }
+
+    /// Read flash.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The start address of the flash to read.
+    /// * `data` - The data.
+    #[cfg(feature = "read-flash")]
+    fn read_flash(&mut self, address: Addr, data: &mut [u8]) -> Result<(), ErrorCode>;
+
+    /// Stop whatever the flash controller is doing. Called by the host after it cancelled
+    /// an operation, which may have left an erase or program running in the controller.
+    ///
+    /// When this returns the controller must be idle, with no operation enabled, its
+    /// error flags cleared and the flash not being written to, so it is safe to reset
+    /// the target or to start over with `Init`. The algorithm stays initialized; the
+    /// host is expected to call `UnInit` next, which runs the usual `Drop`.
+    #[cfg(feature = "abort")]
+    fn abort(&mut self);
+
+    /// Write a snapshot of the flash controller configuration to `out`, for the host to
+    /// show when the algorithm misbehaves. Called by the `DumpConfig` entry point, which
+    /// reads nothing from the flash itself.
+    ///
+    /// The layout is up to the algorithm, the host only shows the bytes. The expected
+    /// one is the controller registers as little endian `u32` values in the order of
+    /// their addresses, so the bytes line up with the reference manual in a hex dump.
+    /// `out` is the buffer the host passed, zeroed before this is called, so whatever
+    /// is not written reads as 0. If it is too short for everything, write what fits or
+    /// return [`ERR_LENGTH`]. Reading a register must not change the state of the
+    /// controller, leave out registers where it would, like FIFOs. The default writes
+    /// nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The buffer to write the snapshot to.
+    #[cfg(feature = "debug-dump")]
+    fn dump_config(&mut self, out: &mut [u8]) -> Result<(), ErrorCode> {
+        let _ = out;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Function {
+    Erase = 1,
+    Program = 2,
+    Verify = 3,
+    #[cfg(feature = "blank-check")]
+    Blank = 4,
+}
+
+impl TryFrom<u32> for Function {
+    type Error = ();
+
+    fn try_from(function: u32) -> Result<Self, Self::Error> {
+        match function {
+            FUNCTION_ERASE => Ok(Function::Erase),
+            FUNCTION_PROGRAM => Ok(Function::Program),
+            FUNCTION_VERIFY => Ok(Function::Verify),
+            #[cfg(feature = "blank-check")]
+            FUNCTION_BLANK => Ok(Function::Blank),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Storage for the algorithm instance created by `Init`.
+///
+/// Flash algorithms run single threaded and are only ever entered by the host one call
+/// at a time, so handing out a pointer to the contents from a shared `static` is sound.
+#[doc(hidden)]
+#[cfg(not(feature = "stateless"))]
+pub struct Instance<T>(core::cell::UnsafeCell<core::mem::MaybeUninit<T>>);
+
+#[cfg(not(feature = "stateless"))]
+unsafe impl<T> Sync for Instance<T> {}
+
+#[cfg(not(feature = "stateless"))]
+impl<T> Instance<T> {
+    pub const fn uninit() -> Self {
+        Self(core::cell::UnsafeCell::new(core::mem::MaybeUninit::uninit()))
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.0.get().cast()
+    }
+}
+
+/// No storage for the algorithm instance, with `stateless`.
+///
+/// The algorithm type has to be zero sized and must not need dropping, which is checked
+/// at compile time. Every value of such a type is the same, so the pointer to the
+/// instance can dangle and writing or dropping through it does nothing.
+#[doc(hidden)]
+#[cfg(feature = "stateless")]
+pub struct Instance<T>(core::marker::PhantomData<T>);
+
+#[cfg(feature = "stateless")]
+unsafe impl<T> Sync for Instance<T> {}
+
+#[cfg(feature = "stateless")]
+impl<T> Instance<T> {
+    pub const fn uninit() -> Self {
+        const {
+            assert!(
+                core::mem::size_of::<T>() == 0 && !core::mem::needs_drop::<T>(),
+                "the `stateless` feature needs a zero sized algorithm type without `Drop`"
+            )
+        };
+        Self(core::marker::PhantomData)
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut T {
+        core::ptr::NonNull::dangling().as_ptr()
+    }
+}
+
+/// Zero-initialized scratch space handed to [`FlashAlgorithm::new()`].
+#[doc(hidden)]
+pub struct Scratch<const N: usize>(core::cell::UnsafeCell<[u8; N]>);
+
+unsafe impl<const N: usize> Sync for Scratch<N> {}
+
+impl<const N: usize> Scratch<N> {
+    pub const fn zeroed() -> Self {
+        Self(core::cell::UnsafeCell::new([0; N]))
+    }
+
+    /// # Safety
+    ///
+    /// The returned buffer must not be aliased, i.e. the previous one must be dead.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get(&'static self) -> &'static mut [u8] {
+        unsafe { &mut *self.0.get() }
+    }
+}
+
+/// The page buffers declared with `page_buffer_count`, aligned for word accesses.
+#[doc(hidden)]
+#[repr(C, align(8))]
+pub struct PageBuffers<const N: usize>(core::cell::UnsafeCell<[u8; N]>);
+
+unsafe impl<const N: usize> Sync for PageBuffers<N> {}
+
+impl<const N: usize> PageBuffers<N> {
+    pub const fn zeroed() -> Self {
+        Self(core::cell::UnsafeCell::new([0; N]))
+    }
+}
+
+/// A macro to define a new flash algoritm.
+///
+/// It takes care of placing the functions in the correct linker sections
+/// and checking the flash algorithm initialization status.
+///
+/// `Init` with a valid function while the algorithm is already initialized first drops
+/// the existing instance as `UnInit` would, ignoring its result, and then creates a new
+/// one. If that fails, whether in the checks of `Init` or in [`FlashAlgorithm::new()`],
+/// the algorithm is left uninitialized: every other entry point returns 1 and `UnInit`
+/// drops nothing, so an instance is never dropped twice or without having been created.
+/// An unknown function code is rejected with 1 before any of this and changes nothing.
+///
+/// Optional fields go between `erase_time_out` and `sectors`, in this order:
+///
+/// - `scratch_size`: the size in bytes of the buffer passed to [`FlashAlgorithm::new()`].
+///   Requires the `scratch-buffer` feature.
+/// - `load_address`: the RAM address the host should load the algorithm to, emitted as
+///   the `LoadAddress` symbol. This corresponds to `load_address` of a probe-rs flash
+///   algorithm definition and has to leave room for the stack and data buffers in the
+///   RAM region that starts at the target's `ram_start`. The host may use it to validate
+///   or default its own configuration; the code itself is position independent and
+///   does not depend on it.
+/// - `config_region`: an option byte region given as `{ address: .., size: .., }`.
+///   `EraseSector`, `ProgramPage` and `ProgramAndVerify` route addresses in
+///   `address..address + size` to [`FlashAlgorithm::erase_option()`] and
+///   [`FlashAlgorithm::program_option()`] instead of the regular methods; the page
+///   index is not computed for them. Requires the `config-region` feature.
+/// - `data_section`: a string literal with the name of the section that is declared
+///   writable with a `.section` directive, `.PrgData` by default. Pass `""` to leave the
+///   directive out and declare the sections in your own linker setup instead.
+/// - `extra_asm`: a list of string literals, the lines of assembly to emit with
+///   `global_asm!` after the `.section` directive of `data_section`, for loaders that
+///   need something the macro does not emit, like a symbol or a section of their own.
+///   The lines are assembled in order, in the section `data_section` switched to, so
+///   anything that emits data or code ends up in `.PrgData` unless it switches to
+///   another section first. The linker drops a section nothing refers to unless it has
+///   the `R` (retain) flag or the linker script keeps it. Nothing checks what they
+///   contain: a line that moves or breaks the descriptor, the entry points or the
+///   section layout breaks the algorithm in ways the host may only notice when it runs
+///   it.
+/// - `min_clock` and `max_clock`: the range of the `clock` argument of `Init`, in Hertz,
+///   that the flash controller works with. They default to `0` and `u32::MAX` and are
+///   available as `MIN_CLOCK` and `MAX_CLOCK` associated consts of the algorithm type.
+///   With the `strict-clock` feature `Init` returns [`ERR_CLOCK`] for a clock outside
+///   of the range without calling [`FlashAlgorithm::new()`], and at least one of them
+///   has to be given.
+/// - `write_align` and `erase_align`: the alignment in bytes, measured from
+///   `flash_address`, that program and erase operations need. They default to
+///   `page_size` and the smallest sector size. They are emitted as the `WriteAlign` and
+///   `EraseAlign` `u32` symbols next to `FlashDevice`, which is not extended to stay
+///   CMSIS-Pack compatible, and are available as `WRITE_ALIGN` and `ERASE_ALIGN`
+///   associated consts. A host that finds the symbols should start every `ProgramPage`
+///   at a multiple of `WriteAlign`, padding the data with the erased value if needed,
+///   and only call `EraseSector` with multiples of `EraseAlign`. With the `strict-align`
+///   feature `EraseSector`, `ProgramPage` and `ProgramAndVerify` return [`ERR_ALIGN`]
+///   for other addresses, except in the `config_region`.
+/// - `symbol_prefix`: a string literal put in front of the name of every symbol the
+///   macro exports, see [Several algorithms in one blob](#several-algorithms-in-one-blob).
+/// - `descriptor_name`: a string literal used as the name of the descriptor symbol
+///   instead of `FlashDevice`, after the `symbol_prefix` if there is one. Only the
+///   descriptor is renamed, for images that already have a `FlashDevice` symbol of their
+///   own. Hosts look for `FlashDevice`, so they have to be told the name, for example
+///   with the `--descriptor` option of the dump tool.
+/// - `reserved`: the `u32` stored in the reserved field of `FlashDevice` after
+///   `page_size`, 0 by default. Some tools read extended attributes from it; parsers that
+///   follow CMSIS-Pack to the letter may expect it to be 0.
+/// - `page_buffer_count`: how many page buffers the algorithm provides for the host to
+///   pipeline writes, 1 by default. It is emitted as the `PageBufferCount` `u32` symbol
+///   and available as the `PAGE_BUFFER_COUNT` associated const. When the field is given
+///   the macro also allocates `page_buffer_count * page_size` zeroed, 8 byte aligned
+///   bytes of RAM in the `.bss.page_buffers` section as the `PageBuffers` symbol.
+///   Buffer `n` starts `n * page_size` bytes into it; the host selects a buffer by
+///   passing its address as the `data` argument of `ProgramPage`, and may fill the next
+///   one while the algorithm programs from the current one. Without the field
+///   `PageBufferCount` is 1 and the host uses its own buffer, as before.
+/// - `min_program_size` and `min_erase_size`: the granularity the controller enforces,
+///   independent of `page_size` and the `sectors`. The length of every program operation
+///   has to be a multiple of `min_program_size`, 1 by default, and the controller always
+///   erases whole blocks of `min_erase_size` bytes, `erase_align` by default, measured
+///   from `flash_address`. A block can span several of the declared sectors, for example
+///   32 KiB blocks over 4 KiB sectors: erasing any of them erases the whole block. They
+///   are emitted as the `MinProgramSize` and `MinEraseSize` `u32` symbols and available
+///   as `MIN_PROGRAM_SIZE` and `MIN_ERASE_SIZE` associated consts. A host that finds the
+///   symbols should pad program data to a multiple of `MinProgramSize` with the erased
+///   value and only erase at multiples of `MinEraseSize`. With the `strict-align`
+///   feature `ProgramPage` and `ProgramAndVerify` return [`ERR_LENGTH`] for other
+///   lengths, and `EraseSector` returns [`ERR_ALIGN`] for an address that is not at the
+///   start of a block, except in the `config_region`.
+/// - `requires_erase`: whether the memory has to be erased before it can be programmed,
+///   `true` by default. Set it to `false` for memories that are overwritten in place,
+///   like EEPROM or FRAM. It is emitted as the `RequiresErase` `u32` symbol, 1 or 0, and
+///   available as the `REQUIRES_ERASE` associated const. A host that finds it set to 0
+///   should skip the erase before programming, which saves time and erase cycles, and
+///   program the data over whatever is there; it still calls `EraseSector` and
+///   `EraseChip` when the user asks for an erase explicitly. Without the symbol the host
+///   erases first, as before.
+/// - `xip`: whether the flash is execute-in-place memory, an external NOR flash that the
+///   device maps into its address space to run code from, `false` by default. It is
+///   emitted as the `Xip` `u32` symbol, 1 or 0, and available as the `XIP` associated
+///   const. It does not change what the algorithm does. A host that finds it set to 1
+///   knows that the memory mapped view of the region may need to be set up or refreshed,
+///   for example by remapping it or invalidating a cache, before it verifies by reading
+///   the flash back through the memory map, and should prefer `Verify` or `ReadFlash`
+///   where the algorithm has them.
+/// - `program_retries`: how many times `ProgramPage` calls
+///   [`FlashAlgorithm::program_page()`] again after it failed with an error that
+///   [`FlashAlgorithm::is_transient()`] accepts, 3 by default. The error of the last
+///   attempt is returned if they all fail. Requires the `retry` feature, and cannot be 0
+///   with it.
+/// - `description`: a `&str` of any length that describes the algorithm for display in
+///   host tools, for example which variants of a chip it supports. It is emitted as the
+///   `FlashAlgorithmDescription` symbol into the `DeviceData` section, UTF-8 followed by
+///   a terminating NUL byte, and does not change `dev_name`. Requires the `description`
+///   feature.
+/// - `controller_base`: the `usize` address of the flash controller's registers,
+///   available as the `CONTROLLER_BASE: Option<usize>` associated const, `None` by
+///   default. Nothing is emitted for the host, it only keeps the controller location
+///   next to the geometry, so a family selected with `#[cfg]` on `const` items changes
+///   both in one place:
+///
+///   ```ignore
+///   const QUADSPI: usize = Algorithm::CONTROLLER_BASE.unwrap();
+///   ```
+/// - `ram_align`: the alignment in bytes, a power of two, that the algorithm needs for
+///   the RAM the host chooses, 4 by default, for controllers whose DMA only reads from
+///   or writes to aligned buffers. It is emitted as the `RamAlign` `u32` symbol and
+///   available as the `RAM_ALIGN` associated const. A host that finds it loads the blob
+///   at a multiple of `RamAlign`, so the algorithm's own statics keep their alignment,
+///   and places every buffer it passes to `ProgramPage`, `Verify`, `ReadFlash` and the
+///   other entry points taking a data pointer at a multiple of it too. Without the
+///   symbol, 4 is enough. The `PageBuffers` of `page_buffer_count` are only 8 byte
+///   aligned, so with a larger `ram_align` a host has to use its own buffers.
+/// - `entry_align`: the alignment in bytes, a power of two of at least 4, of the `.entry`
+///   section with the entry points, for loaders that need the code at a particular
+///   boundary, like 256 bytes. The macro raises the alignment of `.entry` with an
+///   `.balign` directive, so the linker aligns `PrgCode`, which starts with it, without
+///   changes to `memory.x`. It is emitted as the `EntryAlign` `u32` symbol and available
+///   as the `ENTRY_ALIGN` associated const, 4 if not given. The alignment only holds in
+///   RAM if the host loads `PrgCode` at a multiple of it. A host that finds the symbol
+///   picks such a load address. A `load_address` has to be a multiple, which is checked
+///   at compile time, and so has an `ALGO_PLACEMENT_START_ADDRESS` passed to the
+///   linker, or the linker moves `PrgCode` up to the next multiple.
+///
+/// A run of sectors can be marked with `preserve: true` after its `address`, for
+/// calibration or configuration data that has to survive a chip erase. If any run is
+/// marked, `EraseChip` does not call [`FlashAlgorithm::erase_all()`] but erases every
+/// sector of the other runs one by one with [`FlashAlgorithm::erase_sector()`], because
+/// a hardware bulk erase cannot leave sectors out. This takes longer than the bulk erase,
+/// which `erase_time_out` has to allow for. To preserve a single sector of a run, split
+/// the run. `EraseSector` still erases preserved sectors when the host asks for them,
+/// and the flag needs the `erase-chip` feature.
+///
+/// The controller erases whole blocks of `min_erase_size` bytes, so erasing a sector next
+/// to a preserved run would also erase the preserved sectors in the same block. A
+/// preserved run therefore has to start and end on a multiple of `min_erase_size` from
+/// `flash_address`, which is checked at compile time. `EraseChip` erases the other
+/// sectors without the `strict-align` check, as a sector smaller than the block is not
+/// aligned to it, and does not count them in `sectors_erased`.
+///
+/// A run of sectors whose bytes erase to something else than `empty_value`, like a
+/// region that erases to `0x00` next to main flash that erases to `0xFF`, declares that
+/// with `erased: 0x00` after its `address` and `preserve`. `Verify` without data checks
+/// each run against its own value, calling [`FlashAlgorithm::verify_erased()`] once per
+/// run a region spans. The descriptor has only room for `empty_value`, so a host that
+/// only reads the descriptor does not know about the other values, and `BlankCheck`
+/// keeps using the `pattern` the host passes. The algorithm type gets a
+/// `const fn erased_at(address)` that returns the erased value at `address`, for the
+/// methods of the algorithm to use.
+///
+/// A run of sectors that takes longer, or less long, to erase than `erase_time_out`
+/// declares its own timeout in milliseconds with `erase_time_out: 4000` after its
+/// `erased`. Runs without one use `erase_time_out`. The descriptor has only room for
+/// the single value, so the timeouts of all runs are also emitted as the
+/// `SectorEraseTimeouts` symbol next to `FlashDevice`, an array of `u32` with one entry
+/// per entry in `sectors`, in the same order as the sector list of the descriptor. A
+/// host that knows the symbol can use the timeout of the run an `EraseSector` is in
+/// instead of the largest one. `erase_time_out` has to stay at least as large as every
+/// one of them for hosts that do not, which is checked at compile time. `EraseChip`
+/// keeps using `erase_time_out`. The algorithm type gets a
+/// `const fn erase_time_out_at(address)` that returns the timeout at `address`.
+///
+/// The algorithm type gets a `const fn sector_at(address)` that looks up the single
+/// sector containing `address` in `sectors`, returned as a `FlashSector` whose `address`
+/// and `size` fields hold the absolute start and the size of that sector. It returns
+/// `None` outside of the flash, before the first declared sector and in a trailing part
+/// of the flash that is too short for a whole sector.
+///
+/// The runs in `sectors` have to be declared in ascending order of `address`, which is
+/// checked at compile time. The algorithm type gets a `fn sectors()` that iterates over
+/// every single sector as a `FlashSector` like the one from `sector_at`, in ascending
+/// order of address, guaranteed, so an algorithm can rely on it for bank handling.
+/// Gaps between runs are skipped. `EraseChip` with preserved runs erases the other
+/// sectors in the same order.
+///
+/// For erasing a range, the algorithm type gets a `const fn next_sector_boundary(address)`
+/// that returns the start of the sector after the one containing `address`, skipping
+/// gaps, and `None` in and after the last sector:
+///
+/// ```ignore
+/// let mut sector = Some(start);
+/// while let Some(address) = sector.filter(|address| *address < end) {
+///     self.erase_sector(address)?;
+///     sector = Algorithm::next_sector_boundary(address);
+/// }
+/// ```
+///
+/// All numeric fields take any constant expression of the field's type, so the geometry
+/// of a family of chips can be selected with `#[cfg]` on `const` items instead of
+/// duplicating the whole invocation:
+///
+/// ```ignore
+/// #[cfg(feature = "512k")]
+/// const FLASH_SIZE: u32 = 0x8_0000;
+/// #[cfg(feature = "1m")]
+/// const FLASH_SIZE: u32 = 0x10_0000;
+/// const SECTOR_SIZE: u32 = 0x800;
+///
+/// flash_algorithm::algorithm!(Algorithm, {
+///     device_name: "family",
+///     device_type: DeviceType::Onchip,
+///     flash_address: 0x0800_0000,
+///     flash_size: FLASH_SIZE,
+///     page_size: SECTOR_SIZE / 2,
+///     empty_value: 0xFF,
+///     program_time_out: 1000,
+///     erase_time_out: 2000,
+///     sectors: [{
+///         size: SECTOR_SIZE,
+///         address: 0x0,
+///     }]
+/// });
+/// ```
+///
+/// # Several algorithms in one blob
+///
+/// Each invocation exports `Init`, `FlashDevice` and the other symbols under fixed
+/// names, so a second invocation in the same binary collides with the first at link
+/// time. Give each one its own module and a `symbol_prefix` to make them coexist:
+///
+/// ```ignore
+/// mod main_flash {
+///     flash_algorithm::algorithm!(super::MainFlash, { /* .. */ symbol_prefix: "Main", sectors: [/* .. */] });
+/// }
+/// mod data_flash {
+///     flash_algorithm::algorithm!(super::DataFlash, { /* .. */ symbol_prefix: "Data", sectors: [/* .. */] });
+/// }
+/// ```
+///
+/// The host then picks one algorithm by its prefix and uses `<prefix>FlashDevice` as the
+/// descriptor, calls `<prefix>Init`, `<prefix>EraseSector`, `<prefix>ProgramPage` and so
+/// on instead of the CMSIS-Pack names, and reads `<prefix>LoadAddress`,
+/// `<prefix>WriteAlign` and the other optional symbols the same way. Hosts that only
+/// know the standard names, such as probe-rs, can use at most one algorithm, the one
+/// without a prefix. The statics of the crate itself, such as [`TIMEOUT_ELAPSED`] and
+/// the `Verify` state of `verify-chunked`, are shared by all algorithms, which is fine
+/// because the host only ever initializes one at a time.
+#[macro_export]
+macro_rules! algorithm {
+    ($type:ty, {
+        device_name: $device_name:expr,
+        device_type: $device_type:expr,
+        flash_address: $flash_address:expr,
+        flash_size: $flash_size:expr,
+        page_size: $page_size:expr,
+        empty_value: $empty_value:expr,
+        program_time_out: $program_time_out:expr,
+        erase_time_out: $erase_time_out:expr,
+        $(scratch_size: $scratch_size:expr,)?
+        $(load_address: $load_address:expr,)?
+        $(config_region: {
+            address: $config_address:expr,
+            size: $config_size:expr,
+        },)?
+        $(data_section: $data_section:tt,)?
+        $(extra_asm: [$($extra_asm:literal),* $(,)?],)?
+        $(min_clock: $min_clock:expr,)?
+        $(max_clock: $max_clock:expr,)?
+        $(write_align: $write_align:expr,)?
+        $(erase_align: $erase_align:expr,)?
+        $(symbol_prefix: $symbol_prefix:literal,)?
+        $(descriptor_name: $descriptor_name:literal,)?
+        $(reserved: $reserved:expr,)?
+        $(page_buffer_count: $page_buffer_count:expr,)?
+        $(min_program_size: $min_program_size:expr,)?
+        $(min_erase_size: $min_erase_size:expr,)?
+        $(requires_erase: $requires_erase:expr,)?
+        $(xip: $xip:expr,)?
+        $(program_retries: $program_retries:expr,)?
+        $(description: $description:expr,)?
+        $(controller_base: $controller_base:expr,)?
+        $(ram_align: $ram_align:expr,)?
+        $(entry_align: $entry_align:expr,)?
+        sectors: [$({
+            size: $size:expr,
+            address: $address:expr,
+            $(preserve: $preserve:expr,)?
+            $(erased: $erased:expr,)?
+            $(erase_time_out: $sector_erase_time_out:expr,)?
+        }),+]
+    }) => {
+        // The descriptor stores addresses as `Addr`, catch anything that would be truncated.
+        const _: () = {
+            core::assert!(
+                ($flash_address as u128) <= $crate::Addr::MAX as u128,
+                "`flash_address` does not fit in the address type"
+            );
+            core::assert!(
+                ($flash_address as u128) + ($flash_size as u128) <= $crate::Addr::MAX as u128 + 1,
+                "the flash region extends past the end of the address space"
+            );
+            $(
+                core::assert!(
+                    ($address as u128) <= $crate::Addr::MAX as u128,
+                    "sector `address` does not fit in the address type"
+                );
+            )+
+        };
+
+        // The number of sector runs, without the terminating entry of the descriptor.
+        const _SECTOR_COUNT: usize = [$($size as $crate::Addr),+].len();
+
+        const _GEOMETRY: $crate::shim::Geometry<_SECTOR_COUNT> = $crate::shim::Geometry {
+            flash_address: $flash_address,
+            flash_size: $flash_size,
+            page_size: $page_size,
+            empty_value: $empty_value,
+            erase_time_out: $erase_time_out,
+            config_region: $crate::config_region!($($config_address, $config_size)?),
+            sectors: [$($crate::shim::Sector {
+                size: $size,
+                address: $address,
+                preserve: $crate::optional!(false; $($preserve)?),
+                erased: $crate::optional!($empty_value; $($erased)?),
+                erase_time_out: $crate::optional!($erase_time_out; $($sector_erase_time_out)?),
+            }),+],
+            min_clock: <$type>::MIN_CLOCK,
+            max_clock: <$type>::MAX_CLOCK,
+            write_align: <$type>::WRITE_ALIGN,
+            erase_align: <$type>::ERASE_ALIGN,
+            min_program_size: <$type>::MIN_PROGRAM_SIZE,
+            min_erase_size: <$type>::MIN_ERASE_SIZE,
+            program_retries: $crate::program_retries!($($program_retries)?),
+        };
+
+        const _: () = _GEOMETRY.check();
+
+        impl $type {
+            /// The lowest clock in Hertz the algorithm works with.
+            pub const MIN_CLOCK: u32 = $crate::optional!(0; $($min_clock)?);
+            /// The highest clock in Hertz the algorithm works with.
+            pub const MAX_CLOCK: u32 = $crate::optional!(u32::MAX; $($max_clock)?);
+            /// The alignment of program operations, relative to the flash address.
+            pub const WRITE_ALIGN: u32 = $crate::optional!($page_size; $($write_align)?);
+            /// The alignment of erase operations, relative to the flash address.
+            pub const ERASE_ALIGN: u32 = $crate::optional!(
+                $crate::shim::min_sector_size(&[$($size),+]);
+                $($erase_align)?
+            );
+            /// How many page buffers the algorithm provides for the host.
+            pub const PAGE_BUFFER_COUNT: u32 = $crate::optional!(1; $($page_buffer_count)?);
+            /// The size in bytes that the length of every program operation is a multiple of.
+            pub const MIN_PROGRAM_SIZE: u32 = $crate::optional!(1; $($min_program_size)?);
+            /// The size in bytes of the smallest block the controller erases.
+            pub const MIN_ERASE_SIZE: u32 = $crate::optional!(
+                <$type>::ERASE_ALIGN;
+                $($min_erase_size)?
+            );
+            /// Whether the memory has to be erased before it can be programmed.
+            pub const REQUIRES_ERASE: bool = $crate::optional!(true; $($requires_erase)?);
+            /// Whether the flash is execute-in-place memory.
+            pub const XIP: bool = $crate::optional!(false; $($xip)?);
+            /// The address of the flash controller's registers.
+            pub const CONTROLLER_BASE: Option<usize> =
+                $crate::optional!(None; $(Some($controller_base))?);
+            /// The alignment in bytes the host gives the algorithm and the buffers it passes.
+            pub const RAM_ALIGN: u32 = $crate::optional!(4; $($ram_align)?);
+            /// The alignment in bytes of the section with the entry points.
+            pub const ENTRY_ALIGN: u32 = $crate::optional!(4; $($entry_align)?);
+
+            /// The value of an erased byte at `address`, `empty_value` outside of the
+            /// declared sectors.
+            #[allow(dead_code)]
+            pub const fn erased_at(address: $crate::Addr) -> u8 {
+                _GEOMETRY.erased_at(address).0
+            }
+
+            /// How long erasing the sector containing `address` may take in
+            /// milliseconds, `erase_time_out` outside of the declared sectors.
+            #[allow(dead_code)]
+            pub const fn erase_time_out_at(address: $crate::Addr) -> u32 {
+                _GEOMETRY.erase_time_out_at(address)
+            }
+
+            /// The sector containing `address`, with the absolute address of its start,
+            /// or `None` if no declared sector contains it.
+            #[allow(dead_code)]
+            pub const fn sector_at(address: $crate::Addr) -> Option<FlashSector> {
+                match _GEOMETRY.sector_at(address) {
+                    Some(sector) => Some(FlashSector {
+                        size: sector.size,
+                        address: _GEOMETRY.flash_address + sector.address,
+                    }),
+                    None => None,
+                }
+            }
+
+            /// The start of the sector after the one containing `address`, or of the
+            /// first sector after `address` if no sector contains it, `None` in and
+            /// after the last sector.
+            #[allow(dead_code)]
+            pub const fn next_sector_boundary(address: $crate::Addr) -> Option<$crate::Addr> {
+                _GEOMETRY.next_sector_boundary(address)
+            }
+
+            /// Every sector in `sectors`, with the absolute address of its start, in
+            /// ascending order of address.
+            #[allow(dead_code)]
+            pub fn sectors() -> impl Iterator<Item = FlashSector> {
+                $crate::shim::Sectors::new(&_GEOMETRY).map(|sector| FlashSector {
+                    size: sector.size,
+                    address: _GEOMETRY.flash_address + sector.address,
+                })
+            }
+        }
+
+        const _: () = core::assert!(
+            <$type>::PAGE_BUFFER_COUNT >= 1,
+            "`page_buffer_count` must be at least 1"
+        );
+        const _: () = core::assert!(
+            <$type>::MIN_PROGRAM_SIZE >= 1,
+            "`min_program_size` must be at least 1"
+        );
+        const _: () = core::assert!(
+            <$type>::MIN_ERASE_SIZE.is_multiple_of(<$type>::ERASE_ALIGN),
+            "`min_erase_size` must be a multiple of `erase_align`"
+        );
+        const _: () = core::assert!(
+            <$type>::RAM_ALIGN.is_power_of_two() && <$type>::RAM_ALIGN >= 4,
+            "`ram_align` must be a power of two of at least 4"
+        );
+        const _: () = core::assert!(
+            <$type>::ENTRY_ALIGN.is_power_of_two() && <$type>::ENTRY_ALIGN >= 4,
+            "`entry_align` must be a power of two of at least 4"
+        );
+        $(
+            const _: () = core::assert!(
+                ($load_address) % <$type>::ENTRY_ALIGN == 0,
+                "`load_address` must be a multiple of `entry_align`"
+            );
+        )?
+
+        static _IS_INIT: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+        static _ALGO_INSTANCE: $crate::Instance<$type> = $crate::Instance::uninit();
+
+        $crate::data_section!($($data_section)?);
+        $crate::extra_asm!($($($extra_asm),*)?);
+        $crate::entry_align!($($entry_align)?);
+
+        $crate::entry! {
+            [$($symbol_prefix)?]
+            pub unsafe fn Init(addr: $crate::Addr, clock: u32, function: u32) -> u32 {
+                $crate::name_guard!([$($symbol_prefix)?] $device_name);
+                let function = match $crate::Function::try_from(function) {
+                    Ok(function) => function,
+                    // Not a panic, its location would hold an absolute address.
+                    Err(()) => return 1,
+                };
+                if _IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    unsafe { UnInit() };
+                }
+                if let Err(e) = $crate::shim::init(&_GEOMETRY, addr, clock) {
+                    return e.get();
+                }
+                // Only set once there is an instance, so `UnInit` never drops an uninitialized one.
+                match $crate::new_algorithm!($type, addr, clock, function, $($scratch_size)?) {
+                    Ok(inst) => {
+                        unsafe { _ALGO_INSTANCE.as_mut_ptr().write(inst) };
+                        _IS_INIT.store(true, core::sync::atomic::Ordering::Relaxed);
+                        0
+                    }
+                    Err(e) => e.get(),
+                }
+            }
+        }
+        $crate::entry! {
+            [$($symbol_prefix)?]
+            pub unsafe fn UnInit() -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                unsafe { _ALGO_INSTANCE.as_mut_ptr().drop_in_place() };
+                _IS_INIT.store(false, core::sync::atomic::Ordering::Relaxed);
+                $crate::shim::uninit()
+            }
+        }
+        $crate::entry! {
+            [$($symbol_prefix)?]
+            pub unsafe fn EraseSector(addr: $crate::Addr) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                match $crate::shim::erase_sector(this, &_GEOMETRY, addr) {
+                    Ok(()) => 0,
+                    Err(e) => e.get(),
+                }
+            }
+        }
+        $crate::entry! {
+            [$($symbol_prefix)?]
+            pub unsafe fn ProgramPage(addr: $crate::Addr, size: u32, data: *const u8) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                let data_slice: &[u8] = unsafe { core::slice::from_raw_parts(data, size as usize) };
+                match $crate::shim::program_page(this, &_GEOMETRY, addr, data_slice) {
+                    Ok(()) => 0,
+                    Err(e) => e.get(),
+                }
+            }
+        }
+        $crate::entry! {
+            [$($symbol_prefix)?]
+            pub fn Capabilities() -> u32 {
+                $crate::CAPABILITIES
+            }
+        }
+        $crate::erase_chip!($type, [$($symbol_prefix)?]);
+        $crate::read_flash!($type, [$($symbol_prefix)?]);
+        $crate::verify!($type, [$($symbol_prefix)?]);
+        $crate::blank_check!($type, [$($symbol_prefix)?]);
+        $crate::program_and_verify!($type, [$($symbol_prefix)?]);
+        $crate::abort!($type, [$($symbol_prefix)?]);
+        $crate::dump_config!($type, [$($symbol_prefix)?]);
+        $crate::verify_in_place!($type, [$($symbol_prefix)?]);
+        $crate::is_range_blank!($type, [$($symbol_prefix)?]);
+        $crate::standalone!([$($symbol_prefix)?], $flash_address);
+
+        $crate::descriptor_header!([$($symbol_prefix)?], _DESCRIPTOR_VERSION, $device_type);
+
+        // The version is never read by probe-rs and can be fixed.
+        const _DESCRIPTOR_VERSION: u16 = 0x1;
+
+        $crate::flash_device!(
+            [$($symbol_prefix)?],
+            [$($descriptor_name)?],
+            _DESCRIPTOR_VERSION,
+            $device_name,
+            $device_type,
+            $flash_address,
+            $flash_size,
+            $page_size,
+            [$($reserved)?],
+            $empty_value,
+            $program_time_out,
+            $erase_time_out,
+            [$({ $size, $address }),+]
+        );
+
+        $crate::geometry_note!(
+            [$($symbol_prefix)?],
+            $device_name,
+            $device_type,
+            $flash_address,
+            $flash_size,
+            $page_size,
+            $empty_value,
+            $program_time_out,
+            $erase_time_out,
+            [$($size, $address),+]
+        );
+
+        $crate::load_address!([$($symbol_prefix)?], $($load_address)?);
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "WriteAlign")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static WriteAlign: u32 = <$type>::WRITE_ALIGN;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "EraseAlign")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static EraseAlign: u32 = <$type>::ERASE_ALIGN;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "PageBufferCount")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static PageBufferCount: u32 = <$type>::PAGE_BUFFER_COUNT;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "MinProgramSize")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static MinProgramSize: u32 = <$type>::MIN_PROGRAM_SIZE;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "MinEraseSize")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static MinEraseSize: u32 = <$type>::MIN_ERASE_SIZE;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "RequiresErase")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static RequiresErase: u32 = <$type>::REQUIRES_ERASE as u32;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "Xip")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static Xip: u32 = <$type>::XIP as u32;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "RamAlign")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static RamAlign: u32 = <$type>::RAM_ALIGN;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "EntryAlign")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static EntryAlign: u32 = <$type>::ENTRY_ALIGN;
+
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($symbol_prefix,)? "SectorEraseTimeouts")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static SectorEraseTimeouts: [u32; _SECTOR_COUNT] =
+            [$($crate::optional!($erase_time_out; $($sector_erase_time_out)?)),+];
+
+        $crate::page_buffers!([$($symbol_prefix)?], $page_size, $($page_buffer_count)?);
+
+        $crate::feature_manifest!([$($symbol_prefix)?]);
+
+        $crate::description!([$($symbol_prefix)?], $($description)?);
+
+        #[repr(C)]
+        #[derive(Copy, Clone)]
+        pub struct FlashSector {
+            size: $crate::Addr,
+            address: $crate::Addr,
+        }
+
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        #[repr(u16)]
+        pub enum DeviceType {
+            Unknown = 0,
+            Onchip = 1,
+            Ext8Bit = 2,
+            Ext16Bit = 3,
+            Ext32Bit = 4,
+            ExtSpi = 5,
+        }
+
+        // The values of `devType` in CMSIS `FlashOS.h`, which hosts read from the
+        // descriptor, so they must not follow a reordering of the variants.
+        const _: () = {
+            core::assert!(DeviceType::Unknown as u16 == 0);
+            core::assert!(DeviceType::Onchip as u16 == 1);
+            core::assert!(DeviceType::Ext8Bit as u16 == 2);
+            core::assert!(DeviceType::Ext16Bit as u16 == 3);
+            core::assert!(DeviceType::Ext32Bit as u16 == 4);
+            core::assert!(DeviceType::ExtSpi as u16 == 5);
+            core::assert!(core::mem::size_of::<DeviceType>() == 2);
+        };
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "minimal-descriptor"))]
+macro_rules! flash_device {
+    (
+        [$($prefix:literal)?],
+        [$($name:literal)?],
+        $version:expr,
+        $device_name:expr,
+        $device_type:expr,
+        $flash_address:expr,
+        $flash_size:expr,
+        $page_size:expr,
+        [$($reserved:expr)?],
+        $empty_value:expr,
+        $program_time_out:expr,
+        $erase_time_out:expr,
+        [$({ $size:expr, $address:expr }),+]
+    ) => {
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? $crate::optional!("FlashDevice"; $($name)?))]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static FlashDevice: FlashDeviceDescription = FlashDeviceDescription {
+            vers: $version,
+            // The device name here can be customized but it really has no real use
+            // appart from identifying the device the ELF is intended for which we have
+            // in our YAML.
+            dev_name: $crate::arrayify_string($device_name),
+            // The specification does not specify the values that can go here,
+            // but this value means internal flash device.
+            dev_type: $device_type,
+            dev_addr: $flash_address,
+            device_size: $flash_size,
+            page_size: $page_size,
+            _reserved: $crate::optional!(0; $($reserved)?),
+            // The empty state of a byte in flash.
+            empty: $empty_value,
+            // This value can be used to estimate the amount of time the flashing procedure takes worst case.
+            program_time_out: $program_time_out,
+            // This value can be used to estimate the amount of time the erasing procedure takes worst case.
+            erase_time_out: $erase_time_out,
+            flash_sectors: [
+                $(
+                    FlashSector {
+                        size: $size,
+                        address: $address,
+                    }
+                ),+,
+                // This marks the end of the flash sector list.
+                FlashSector {
+                    size: $crate::Addr::MAX,
+                    address: $crate::Addr::MAX,
+                }
+            ],
+        };
+
+        #[repr(C)]
+        pub struct FlashDeviceDescription {
+            vers: u16,
+            dev_name: [u8; 128],
+            dev_type: DeviceType,
+            dev_addr: $crate::Addr,
+            device_size: $crate::Addr,
+            page_size: u32,
+            _reserved: u32,
+            empty: u8,
+            program_time_out: u32,
+            erase_time_out: u32,
+
+            flash_sectors: [FlashSector; _SECTOR_COUNT + 1],
+        }
+
+        // Hosts parse the descriptor with these offsets, it must never change by accident.
+        const _: () = {
+            use core::mem::offset_of;
+            let layout = $crate::DESCRIPTOR_LAYOUT;
+            core::assert!(
+                offset_of!(FlashDeviceDescription, vers) == 0
+                    && offset_of!(FlashDeviceDescription, dev_name) == 2
+                    && offset_of!(FlashDeviceDescription, dev_type) == 130
+                    && offset_of!(FlashDeviceDescription, dev_addr) == layout.dev_addr
+                    && offset_of!(FlashDeviceDescription, device_size) == layout.device_size
+                    && offset_of!(FlashDeviceDescription, page_size) == layout.page_size
+                    && offset_of!(FlashDeviceDescription, _reserved) == layout.reserved
+                    && offset_of!(FlashDeviceDescription, empty) == layout.empty
+                    && offset_of!(FlashDeviceDescription, program_time_out) == layout.program_time_out
+                    && offset_of!(FlashDeviceDescription, erase_time_out) == layout.erase_time_out
+                    && offset_of!(FlashDeviceDescription, flash_sectors) == layout.sectors,
+                "the layout of `FlashDevice` differs from `DESCRIPTOR_LAYOUT`"
+            );
+            core::assert!(
+                core::mem::size_of::<FlashDeviceDescription>()
+                    == $crate::descriptor_len(_SECTOR_COUNT),
+                "the size of `FlashDevice` differs from `descriptor_len()`"
+            );
+        };
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "minimal-descriptor")]
+macro_rules! flash_device {
+    (
+        [$($prefix:literal)?],
+        [$($name:literal)?],
+        $version:expr,
+        $device_name:expr,
+        $device_type:expr,
+        $flash_address:expr,
+        $flash_size:expr,
+        $page_size:expr,
+        [$($reserved:expr)?],
+        $empty_value:expr,
+        $program_time_out:expr,
+        $erase_time_out:expr,
+        [$({ $size:expr, $address:expr }),+]
+    ) => {
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? $crate::optional!("FlashDevice"; $($name)?))]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static FlashDevice: FlashDeviceDescription = FlashDeviceDescription {
+            magic: $crate::MINIMAL_DESCRIPTOR_MAGIC,
+            vers: $version,
+            _padding: 0,
+            flash_sectors: [
+                $(
+                    FlashSector {
+                        size: $size,
+                        address: $address,
+                    }
+                ),+,
+                // This marks the end of the flash sector list.
+                FlashSector {
+                    size: $crate::Addr::MAX,
+                    address: $crate::Addr::MAX,
+                }
+            ],
+        };
+
+        #[repr(C)]
+        pub struct FlashDeviceDescription {
+            magic: [u8; 4],
+            vers: u16,
+            _padding: u16,
+
+            flash_sectors: [FlashSector; _SECTOR_COUNT + 1],
+        }
+
+        // Hosts parse the descriptor with these offsets, it must never change by accident.
+        const _: () = {
+            use core::mem::offset_of;
+            core::assert!(
+                offset_of!(FlashDeviceDescription, magic) == 0
+                    && offset_of!(FlashDeviceDescription, vers) == 4
+                    && offset_of!(FlashDeviceDescription, flash_sectors) == 8,
+                "the layout of the minimal `FlashDevice` differs from its documentation"
+            );
+            core::assert!(
+                core::mem::size_of::<FlashDeviceDescription>()
+                    == $crate::descriptor_len(_SECTOR_COUNT),
+                "the size of `FlashDevice` differs from `descriptor_len()`"
+            );
+        };
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "descriptor-header"))]
+macro_rules! descriptor_header {
+    ([$($prefix:literal)?], $version:expr, $device_type:expr) => {};
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "descriptor-header")]
+macro_rules! descriptor_header {
+    ([$($prefix:literal)?], $version:expr, $device_type:expr) => {
+        // In its own input section, which `memory.x` places first in `DeviceData`.
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "FlashAlgorithmHeader")]
+        #[used]
+        #[link_section = "DeviceData.header"]
+        pub static FlashAlgorithmHeader: $crate::DescriptorHeader =
+            $crate::DescriptorHeader::new($version, $device_type as u16);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "feature-manifest"))]
+macro_rules! feature_manifest {
+    ([$($prefix:literal)?]) => {};
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "feature-manifest")]
+macro_rules! feature_manifest {
+    ([$($prefix:literal)?]) => {
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "FlashAlgorithmFeatures")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static FlashAlgorithmFeatures: [u8; $crate::FEATURE_MANIFEST.len()] =
+            $crate::FEATURE_MANIFEST;
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "geometry-note"))]
+macro_rules! geometry_note {
+    ($($tt:tt)*) => {};
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "geometry-note")]
+macro_rules! geometry_note {
+    (
+        [$($prefix:literal)?],
+        $device_name:expr,
+        $device_type:expr,
+        $flash_address:expr,
+        $flash_size:expr,
+        $page_size:expr,
+        $empty_value:expr,
+        $program_time_out:expr,
+        $erase_time_out:expr,
+        [$($size:expr, $address:expr),+]
+    ) => {
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "FlashDeviceNote")]
+        #[used]
+        #[link_section = "GeometryNote"]
+        pub static FlashDeviceNote: [u8; $crate::note_len(
+            $device_name.len(),
+            _SECTOR_COUNT,
+        )] = $crate::Note::empty()
+            .str("device_name=")
+            .str($device_name)
+            .str("\n")
+            .dec_line("device_type", $device_type as u64)
+            .hex_line("flash_address", $flash_address as u64)
+            .hex_line("flash_size", $flash_size as u64)
+            .hex_line("page_size", $page_size as u64)
+            .hex_line("empty_value", $empty_value as u64)
+            .dec_line("program_time_out", $program_time_out as u64)
+            .dec_line("erase_time_out", $erase_time_out as u64)
+            $(
+                .str("sector=")
+                .hex($size as u64)
+                .str("@")
+                .hex($address as u64)
+                .str("\n")
+            )+
+            .finish();
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "scratch-buffer"))]
+macro_rules! new_algorithm {
+    ($type:ty, $addr:expr, $clock:expr, $function:expr,) => {
+        $crate::shim::new::<$type>($addr, $clock, $function)
+    };
+    ($type:ty, $addr:expr, $clock:expr, $function:expr, $scratch_size:expr) => {
+        core::compile_error!("`scratch_size` requires the `scratch-buffer` feature")
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "scratch-buffer")]
+macro_rules! new_algorithm {
+    ($type:ty, $addr:expr, $clock:expr, $function:expr,) => {
+        core::compile_error!("the `scratch-buffer` feature requires a `scratch_size`")
+    };
+    ($type:ty, $addr:expr, $clock:expr, $function:expr, $scratch_size:expr) => {{
+        #[link_section = ".bss.scratch"]
+        static SCRATCH: $crate::Scratch<{ $scratch_size }> = $crate::Scratch::zeroed();
+        // The previous instance, if any, was dropped by `UnInit` above.
+        $crate::shim::new::<$type>($addr, $clock, $function, unsafe { SCRATCH.get() })
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "description"))]
+macro_rules! description {
+    ([$($prefix:literal)?],) => {};
+    ([$($prefix:literal)?], $description:expr) => {
+        core::compile_error!("`description` requires the `description` feature");
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "description")]
+macro_rules! description {
+    ([$($prefix:literal)?],) => {};
+    ([$($prefix:literal)?], $description:expr) => {
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "FlashAlgorithmDescription")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static FlashAlgorithmDescription: [u8; $description.len() + 1] =
+            $crate::arrayify_string($description);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "retry"))]
+macro_rules! program_retries {
+    () => {
+        0
+    };
+    ($retries:expr) => {
+        core::compile_error!("`program_retries` requires the `retry` feature")
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "retry")]
+macro_rules! program_retries {
+    () => {
+        3
+    };
+    ($retries:expr) => {
+        $retries
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "config-region"))]
+macro_rules! config_region {
+    () => {
+        0..0
+    };
+    ($address:expr, $size:expr) => {
+        core::compile_error!("`config_region` requires the `config-region` feature")
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "config-region")]
+macro_rules! config_region {
+    () => {
+        core::compile_error!("the `config-region` feature requires a `config_region`")
+    };
+    ($address:expr, $size:expr) => {
+        $address..$address + $size
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! optional {
+    ($default:expr;) => {
+        $default
+    };
+    ($default:expr; $value:expr) => {
+        $value
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "cmse"))]
+macro_rules! entry {
+    ([$($prefix:literal)?] pub unsafe fn $name:ident($($arg:ident: $type:ty),* $(,)?) -> u32 $body:block) => {
+        #[export_name = concat!($($prefix,)? stringify!($name))]
+        #[link_section = ".entry"]
+        pub unsafe extern "C" fn $name($($arg: $type),*) -> u32 {
+            $crate::fp_save!();
+            $body
+        }
+    };
+    ([$($prefix:literal)?] pub fn $name:ident() -> u32 $body:block) => {
+        #[export_name = concat!($($prefix,)? stringify!($name))]
+        #[link_section = ".entry"]
+        pub extern "C" fn $name() -> u32 {
+            $crate::fp_save!();
+            $body
+        }
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "cmse")]
+macro_rules! entry {
+    ([$($prefix:literal)?] pub unsafe fn $name:ident($($arg:ident: $type:ty),* $(,)?) -> u32 $body:block) => {
+        #[export_name = concat!($($prefix,)? stringify!($name))]
+        #[link_section = ".entry"]
+        pub unsafe extern "cmse-nonsecure-entry" fn $name($($arg: $type),*) -> u32 {
+            $crate::fp_save!();
+            $body
+        }
+    };
+    ([$($prefix:literal)?] pub fn $name:ident() -> u32 $body:block) => {
+        #[export_name = concat!($($prefix,)? stringify!($name))]
+        #[link_section = ".entry"]
+        pub extern "cmse-nonsecure-entry" fn $name() -> u32 {
+            $crate::fp_save!();
+            $body
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "name-guard"))]
+macro_rules! name_guard {
+    ([$($prefix:literal)?] $device_name:expr) => {};
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "name-guard")]
+macro_rules! name_guard {
+    ([$($prefix:literal)?] $device_name:expr) => {
+        // Only written by the host, through the debug port.
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "ExpectedNameHash")]
+        #[used]
+        static ExpectedNameHash: core::sync::atomic::AtomicU32 =
+            core::sync::atomic::AtomicU32::new(0);
+        if ExpectedNameHash.load(core::sync::atomic::Ordering::Relaxed)
+            != const { $crate::Crc32::of($device_name.as_bytes()) }
+        {
+            return $crate::ERR_NAME.get();
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "fp-save"))]
+macro_rules! fp_save {
+    () => {};
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "fp-save")]
+macro_rules! fp_save {
+    () => {
+        let mut _fp = $crate::FpState::new();
+        _fp.save();
+    };
+}
 
-    /// Read flash.
-    ///
-    /// # Arguments
-    ///
-    /// * `address` - The start address of the flash to read.
-    /// * `data` - The data.
-    #[cfg(feature = "read-flash")]
-    fn read_flash(&mut self, address: u32, data: &mut [u8]) -> Result<(), ErrorCode>;
+#[doc(hidden)]
+#[macro_export]
+macro_rules! load_address {
+    ([$($prefix:literal)?],) => {};
+    ([$($prefix:literal)?], $load_address:expr) => {
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "LoadAddress")]
+        #[used]
+        #[link_section = "DeviceData"]
+        pub static LoadAddress: u32 = $load_address;
+    };
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum Function {
-    Erase = 1,
-    Program = 2,
-    Verify = 3,
+#[doc(hidden)]
+#[macro_export]
+macro_rules! page_buffers {
+    ([$($prefix:literal)?], $page_size:expr,) => {};
+    ([$($prefix:literal)?], $page_size:expr, $page_buffer_count:expr) => {
+        // Only written by the host, through the debug port.
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "PageBuffers")]
+        #[used]
+        #[link_section = ".bss.page_buffers"]
+        pub static PageBuffers: $crate::PageBuffers<{ ($page_buffer_count * $page_size) as usize }> =
+            $crate::PageBuffers::zeroed();
+    };
 }
 
-/// A macro to define a new flash algoritm.
-///
-/// It takes care of placing the functions in the correct linker sections
-/// and checking the flash algorithm initialization status.
+#[doc(hidden)]
 #[macro_export]
-macro_rules! algorithm {
-    ($type:ty, {
-        device_name: $device_name:expr,
-        device_type: $device_type:expr,
-        flash_address: $flash_address:expr,
-        flash_size: $flash_size:expr,
-        page_size: $page_size:expr,
-        empty_value: $empty_value:expr,
-        program_time_out: $program_time_out:expr,
-        erase_time_out: $erase_time_out:expr,
-        sectors: [$({
-            size: $size:expr,
-            address: $address:expr,
-        }),+]
-    }) => {
-        static mut _IS_INIT: bool = false;
-        static mut _ALGO_INSTANCE: core::mem::MaybeUninit<$type> = core::mem::MaybeUninit::uninit();
+macro_rules! data_section {
+    () => {
+        $crate::data_section!(".PrgData");
+    };
+    ("") => {};
+    ($name:literal) => {
+        core::arch::global_asm!(core::concat!(".section ", $name, ", \"aw\""));
+    };
+}
 
-        core::arch::global_asm!(".section .PrgData, \"aw\"");
+#[doc(hidden)]
+#[macro_export]
+macro_rules! extra_asm {
+    () => {};
+    ($($line:literal),+) => {
+        core::arch::global_asm!($($line),+);
+    };
+}
 
-        #[no_mangle]
-        #[link_section = ".entry"]
-        pub unsafe extern "C" fn Init(addr: u32, clock: u32, function: u32) -> u32 {
-            if _IS_INIT {
-                UnInit();
-            }
-            _IS_INIT = true;
-            let function = match function {
-                1 => $crate::Function::Erase,
-                2 => $crate::Function::Program,
-                3 => $crate::Function::Verify,
-                _ => core::panic!("This branch can only be reached if the host library sent an unknown function code.")
-            };
-            match <$type as $crate::FlashAlgorithm>::new(addr, clock, function) {
-                Ok(inst) => {
-                    _ALGO_INSTANCE.as_mut_ptr().write(inst);
-                    _IS_INIT = true;
-                    0
-                }
-                Err(e) => e.get(),
+#[doc(hidden)]
+#[macro_export]
+macro_rules! entry_align {
+    () => {};
+    ($align:expr) => {
+        // An empty part of `.entry` that only raises the alignment of the section.
+        core::arch::global_asm!(
+            ".pushsection .entry, \"ax\"",
+            ".balign {align}",
+            ".popsection",
+            align = const $align,
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "stub-unsupported"))]
+macro_rules! unsupported {
+    ([$($prefix:literal)?] $name:ident($($arg:ident: $type:ty),*)) => {};
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "stub-unsupported")]
+macro_rules! unsupported {
+    ([$($prefix:literal)?] $name:ident($($arg:ident: $type:ty),*)) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn $name($($arg: $type),*) -> u32 {
+                $crate::ERR_UNSUPPORTED.get()
             }
         }
-        #[no_mangle]
-        #[link_section = ".entry"]
-        pub unsafe extern "C" fn UnInit() -> u32 {
-            if !_IS_INIT {
-                return 1;
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "erase-chip"))]
+macro_rules! erase_chip {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] EraseChip());
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "erase-chip")]
+macro_rules! erase_chip {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn EraseChip() -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                match $crate::shim::erase_all(this, &_GEOMETRY) {
+                    Ok(()) => 0,
+                    Err(e) => e.get(),
+                }
             }
-            _ALGO_INSTANCE.as_mut_ptr().drop_in_place();
-            _IS_INIT = false;
-            0
         }
-        #[no_mangle]
-        #[link_section = ".entry"]
-        pub unsafe extern "C" fn EraseSector(addr: u32) -> u32 {
-            if !_IS_INIT {
-                return 1;
-            }
-            let this = &mut *_ALGO_INSTANCE.as_mut_ptr();
-            match <$type as $crate::FlashAlgorithm>::erase_sector(this, addr) {
-                Ok(()) => 0,
-                Err(e) => e.get(),
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "abort"))]
+macro_rules! abort {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] Abort());
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "abort")]
+macro_rules! abort {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn Abort() -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                <$type as $crate::FlashAlgorithm>::abort(this);
+                0
             }
         }
-        #[no_mangle]
-        #[link_section = ".entry"]
-        pub unsafe extern "C" fn ProgramPage(addr: u32, size: u32, data: *const u8) -> u32 {
-            if !_IS_INIT {
-                return 1;
-            }
-            let this = &mut *_ALGO_INSTANCE.as_mut_ptr();
-            let data_slice: &[u8] = unsafe { core::slice::from_raw_parts(data, size as usize) };
-            match <$type as $crate::FlashAlgorithm>::program_page(this, addr, data_slice) {
-                Ok(()) => 0,
-                Err(e) => e.get(),
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "debug-dump"))]
+macro_rules! dump_config {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] DumpConfig(_data: *mut u8, _size: u32));
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "debug-dump")]
+macro_rules! dump_config {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn DumpConfig(data: *mut u8, size: u32) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                let data_slice: &mut [u8] =
+                    unsafe { core::slice::from_raw_parts_mut(data, size as usize) };
+                data_slice.fill(0);
+                match <$type as $crate::FlashAlgorithm>::dump_config(this, data_slice) {
+                    Ok(()) => 0,
+                    Err(e) => e.get(),
+                }
             }
         }
-        $crate::erase_chip!($type);
-        $crate::read_flash!($type);
-        $crate::verify!($type);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "standalone"))]
+macro_rules! standalone {
+    ([$($prefix:literal)?], $flash_address:expr) => {};
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "standalone")]
+macro_rules! standalone {
+    ([$($prefix:literal)?], $flash_address:expr) => {
+        #[allow(non_upper_case_globals)]
+        #[export_name = concat!($($prefix,)? "StandaloneStack")]
+        #[link_section = ".bss.standalone_stack"]
+        pub static StandaloneStack: $crate::StandaloneStack = $crate::StandaloneStack::zeroed();
 
         #[allow(non_upper_case_globals)]
-        #[no_mangle]
+        #[export_name = concat!($($prefix,)? "StandaloneVectors")]
         #[used]
-        #[link_section = "DeviceData"]
-        pub static FlashDevice: FlashDeviceDescription = FlashDeviceDescription {
-            // The version is never read by probe-rs and can be fixed.
-            vers: 0x1,
-            // The device name here can be customized but it really has no real use
-            // appart from identifying the device the ELF is intended for which we have
-            // in our YAML.
-            dev_name: $crate::arrayify_string($device_name),
-            // The specification does not specify the values that can go here,
-            // but this value means internal flash device.
-            dev_type: $device_type,
-            dev_addr: $flash_address,
-            device_size: $flash_size,
-            page_size: $page_size,
-            _reserved: 0,
-            // The empty state of a byte in flash.
-            empty: $empty_value,
-            // This value can be used to estimate the amount of time the flashing procedure takes worst case.
-            program_time_out: $program_time_out,
-            // This value can be used to estimate the amount of time the erasing procedure takes worst case.
-            erase_time_out: $erase_time_out,
-            flash_sectors: [
-                $(
-                    FlashSector {
-                        size: $size,
-                        address: $address,
-                    }
-                ),+,
-                // This marks the end of the flash sector list.
-                FlashSector {
-                    size: 0xffff_ffff,
-                    address: 0xffff_ffff,
-                }
-            ],
+        #[link_section = ".standalone.vectors"]
+        pub static StandaloneVectors: $crate::StandaloneVectors = $crate::StandaloneVectors {
+            stack_top: StandaloneStack.top(),
+            reset: StandaloneReset,
         };
 
-        #[repr(C)]
-        pub struct FlashDeviceDescription {
-            vers: u16,
-            dev_name: [u8; 128],
-            dev_type: DeviceType,
-            dev_addr: u32,
-            device_size: u32,
-            page_size: u32,
-            _reserved: u32,
-            empty: u8,
-            program_time_out: u32,
-            erase_time_out: u32,
-
-            flash_sectors: [FlashSector; $crate::count!($($size)*) + 1],
-        }
-
-        #[repr(C)]
-        #[derive(Copy, Clone)]
-        pub struct FlashSector {
-            size: u32,
-            address: u32,
+        #[export_name = concat!($($prefix,)? "StandaloneReset")]
+        pub unsafe extern "C" fn StandaloneReset() -> ! {
+            let status = unsafe { Init($flash_address as $crate::Addr, 0, $crate::Function::Erase as u32) };
+            match status {
+                0 => $crate::halt(unsafe { UnInit() }),
+                _ => $crate::halt(status),
+            }
         }
+    };
+}
 
-        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-        #[repr(u16)]
-        pub enum DeviceType {
-            Unknown = 0,
-            Onchip = 1,
-            Ext8Bit = 2,
-            Ext16Bit = 3,
-            Ext32Bit = 4,
-            ExtSpi = 5,
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "verify-in-place"))]
+macro_rules! verify_in_place {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] VerifyInPlace(_addr: $crate::Addr, _data: *const u8, _size: u32));
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "verify-in-place")]
+macro_rules! verify_in_place {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn VerifyInPlace(addr: $crate::Addr, data: *const u8, size: u32) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                if data.is_null() || (data as usize).checked_add(size as usize).is_none() {
+                    return $crate::ERR_OUT_OF_BOUNDS.get();
+                }
+                let data_slice: &[u8] = unsafe { core::slice::from_raw_parts(data, size as usize) };
+                match $crate::shim::verify_in_place(this, &_GEOMETRY, addr, data_slice) {
+                    Ok(()) => 0,
+                    Err(e) => e.get(),
+                }
+            }
         }
     };
 }
 
 #[doc(hidden)]
 #[macro_export]
-#[cfg(not(feature = "erase-chip"))]
-macro_rules! erase_chip {
-    ($type:ty) => {};
+#[cfg(not(feature = "blank-query"))]
+macro_rules! is_range_blank {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] IsRangeBlank(_addr: $crate::Addr, _size: u32, _blank: *mut u32));
+    };
 }
 #[doc(hidden)]
 #[macro_export]
-#[cfg(feature = "erase-chip")]
-macro_rules! erase_chip {
-    ($type:ty) => {
-        #[no_mangle]
-        #[link_section = ".entry"]
-        pub unsafe extern "C" fn EraseChip() -> u32 {
-            if !_IS_INIT {
-                return 1;
-            }
-            let this = &mut *_ALGO_INSTANCE.as_mut_ptr();
-            match <$type as $crate::FlashAlgorithm>::erase_all(this) {
-                Ok(()) => 0,
-                Err(e) => e.get(),
+#[cfg(feature = "blank-query")]
+macro_rules! is_range_blank {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn IsRangeBlank(addr: $crate::Addr, size: u32, blank: *mut u32) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                if blank.is_null() {
+                    return $crate::ERR_OUT_OF_BOUNDS.get();
+                }
+                match $crate::shim::is_range_blank(this, &_GEOMETRY, addr, size) {
+                    Ok(is_blank) => {
+                        unsafe { blank.write_volatile(is_blank as u32) };
+                        0
+                    }
+                    Err(e) => e.get(),
+                }
             }
         }
     };
@@ -284,24 +2753,28 @@ macro_rules! erase_chip {
 #[macro_export]
 #[cfg(not(feature = "read-flash"))]
 macro_rules! read_flash {
-    ($type:ty) => {};
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] ReadFlash(_addr: $crate::Addr, _size: u32, _data: *mut u8));
+    };
 }
 #[doc(hidden)]
 #[macro_export]
 #[cfg(feature = "read-flash")]
 macro_rules! read_flash {
-    ($type:ty) => {
-        #[no_mangle]
-        #[link_section = ".entry"]
-        pub unsafe extern "C" fn ReadFlash(addr: u32, size: u32, data: *mut u8) -> u32 {
-            if !_IS_INIT {
-                return 1;
-            }
-            let this = &mut *_ALGO_INSTANCE.as_mut_ptr();
-            let data_slice: &mut [u8] = unsafe { core::slice::from_raw_parts_mut(data, size as usize) };
-            match <$type as $crate::FlashAlgorithm>::read_flash(this, addr, data_slice) {
-                Ok(()) => 0,
-                Err(e) => e.get(),
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn ReadFlash(addr: $crate::Addr, size: u32, data: *mut u8) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                let data_slice: &mut [u8] =
+                    unsafe { core::slice::from_raw_parts_mut(data, size as usize) };
+                match <$type as $crate::FlashAlgorithm>::read_flash(this, addr, data_slice) {
+                    Ok(()) => 0,
+                    Err(e) => e.get(),
+                }
             }
         }
     };
@@ -311,30 +2784,89 @@ macro_rules! read_flash {
 #[macro_export]
 #[cfg(not(feature = "verify"))]
 macro_rules! verify {
-    ($type:ty) => {};
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] Verify(_addr: $crate::Addr, _size: u32, _data: *const u8));
+    };
 }
 #[doc(hidden)]
 #[macro_export]
 #[cfg(feature = "verify")]
 macro_rules! verify {
-    ($type:ty) => {
-        #[no_mangle]
-        #[link_section = ".entry"]
-        pub unsafe extern "C" fn Verify(addr: u32, size: u32, data: *const u8) -> u32 {
-            if !_IS_INIT {
-                return 1;
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn Verify(addr: $crate::Addr, size: u32, data: *const u8) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+
+                if data.is_null() {
+                    $crate::shim::verify_without_data(this, &_GEOMETRY, addr, size)
+                } else {
+                    let data_slice: &[u8] = unsafe { core::slice::from_raw_parts(data, size as usize) };
+                    $crate::shim::verify(this, addr, data_slice)
+                }
             }
-            let this = &mut *_ALGO_INSTANCE.as_mut_ptr();
+        }
+    };
+}
 
-            if data.is_null() {
-                match <$type as $crate::FlashAlgorithm>::verify(this, addr, size, None) {
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "blank-check"))]
+macro_rules! blank_check {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] BlankCheck(_addr: $crate::Addr, _size: u32, _pattern: u8));
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "blank-check")]
+macro_rules! blank_check {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn BlankCheck(addr: $crate::Addr, size: u32, pattern: u8) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
+                match <$type as $crate::FlashAlgorithm>::blank_check(this, addr, size, pattern) {
                     Ok(()) => 0,
                     Err(e) => e.get(),
                 }
-            } else {
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "program-verify"))]
+macro_rules! program_and_verify {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::unsupported!([$($prefix)?] ProgramAndVerify(_addr: $crate::Addr, _size: u32, _data: *const u8));
+    };
+}
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "program-verify")]
+macro_rules! program_and_verify {
+    ($type:ty, [$($prefix:literal)?]) => {
+        $crate::entry! {
+            [$($prefix)?]
+            pub unsafe fn ProgramAndVerify(
+                addr: $crate::Addr,
+                size: u32,
+                data: *const u8,
+            ) -> u32 {
+                if !_IS_INIT.load(core::sync::atomic::Ordering::Relaxed) {
+                    return 1;
+                }
+                let this = unsafe { &mut *_ALGO_INSTANCE.as_mut_ptr() };
                 let data_slice: &[u8] = unsafe { core::slice::from_raw_parts(data, size as usize) };
-                match <$type as $crate::FlashAlgorithm>::verify(this, addr, size, Some(data_slice))
-                {
+                match $crate::shim::program_and_verify(this, &_GEOMETRY, addr, data_slice) {
                     Ok(()) => 0,
                     Err(e) => e.get(),
                 }
@@ -343,11 +2875,77 @@ macro_rules! verify {
     };
 }
 
-#[doc(hidden)]
-#[macro_export]
-macro_rules! count {
-    () => (0usize);
-    ( $x:tt $($xs:tt)* ) => (1usize + $crate::count!($($xs)*));
+/// Byte offsets of the fields of `FlashDevice` that follow `dev_type`, whose position
+/// depends on the width of [`Addr`].
+///
+/// `vers` is at 0, `dev_name` at 2 and `dev_type` at 130 in every layout. These offsets
+/// are the ABI hosts parse the descriptor with, and every invocation of [`algorithm!`]
+/// checks at compile time that its descriptor has exactly this layout, so a change to
+/// them is always deliberate and has to be announced as a breaking change. The
+/// `dump` tool has a copy of both layouts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DescriptorLayout {
+    pub dev_addr: usize,
+    pub device_size: usize,
+    pub page_size: usize,
+    pub reserved: usize,
+    pub empty: usize,
+    pub program_time_out: usize,
+    pub erase_time_out: usize,
+    /// The start of the sector list, which is also the size of the fixed fields.
+    pub sectors: usize,
+}
+
+/// The layout of `FlashDevice` with the enabled features, see [`DescriptorLayout`].
+///
+/// With `minimal-descriptor` this is not the layout of `FlashDevice`, see
+/// [Minimal descriptor](index.html#minimal-descriptor).
+#[cfg(not(feature = "addr64"))]
+pub const DESCRIPTOR_LAYOUT: DescriptorLayout = DescriptorLayout {
+    dev_addr: 132,
+    device_size: 136,
+    page_size: 140,
+    reserved: 144,
+    empty: 148,
+    program_time_out: 152,
+    erase_time_out: 156,
+    sectors: 160,
+};
+/// The layout of `FlashDevice` with the enabled features, see [`DescriptorLayout`].
+///
+/// With `minimal-descriptor` this is not the layout of `FlashDevice`, see
+/// [Minimal descriptor](index.html#minimal-descriptor).
+#[cfg(feature = "addr64")]
+pub const DESCRIPTOR_LAYOUT: DescriptorLayout = DescriptorLayout {
+    dev_addr: 136,
+    device_size: 144,
+    page_size: 152,
+    reserved: 156,
+    empty: 160,
+    program_time_out: 164,
+    erase_time_out: 168,
+    sectors: 176,
+};
+
+/// The first bytes of the `FlashDevice` descriptor with `minimal-descriptor`, see
+/// [Minimal descriptor](index.html#minimal-descriptor).
+pub const MINIMAL_DESCRIPTOR_MAGIC: [u8; 4] = *b"FLAM";
+
+/// The size in bytes of the `FlashDevice` descriptor that [`algorithm!`] emits for
+/// `sectors` entries in its `sectors` list, so a host can read exactly the descriptor out
+/// of `DeviceData`.
+///
+/// It is `base + (sectors + 1) * size_of::<FlashSector>()`, counting the terminating
+/// sector entry. `base` is [`DESCRIPTOR_LAYOUT`]`.sectors`, 160, and a sector entry 8
+/// bytes, or 176 and 16 bytes with `addr64`. With `minimal-descriptor` `base` is 8. A
+/// host reading an ELF can also take the size of the `FlashDevice` symbol.
+pub const fn descriptor_len(sectors: usize) -> usize {
+    let base = if cfg!(feature = "minimal-descriptor") {
+        8
+    } else {
+        DESCRIPTOR_LAYOUT.sectors
+    };
+    base + (sectors + 1) * 2 * core::mem::size_of::<Addr>()
 }
 
 pub const fn arrayify_string<const N: usize>(msg: &'static str) -> [u8; N] {
@@ -362,3 +2960,40 @@ pub const fn arrayify_string<const N: usize>(msg: &'static str) -> [u8; N] {
 
     arr
 }
+
+const fn all_bytes(bytes: &[u8], from: usize, value: u8) -> bool {
+    let mut i = from;
+    while i < bytes.len() {
+        if bytes[i] != value {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// probe-rs reads `dev_name` up to the first NUL, so a short name has to be followed by
+// zeros all the way to the end. A name of 128 bytes or more fills the whole array and
+// leaves no room for the terminator.
+const _: () = {
+    let short: [u8; 128] = arrayify_string("abc");
+    assert!(short[0] == b'a' && short[1] == b'b' && short[2] == b'c');
+    assert!(all_bytes(&short, 3, 0));
+
+    let empty: [u8; 128] = arrayify_string("");
+    assert!(all_bytes(&empty, 0, 0));
+
+    const FULL: &str = match core::str::from_utf8(&[b'x'; 128]) {
+        Ok(name) => name,
+        Err(_) => panic!(),
+    };
+    let full: [u8; 128] = arrayify_string(FULL);
+    assert!(all_bytes(&full, 0, b'x'));
+
+    const LONG: &str = match core::str::from_utf8(&[b'y'; 130]) {
+        Ok(name) => name,
+        Err(_) => panic!(),
+    };
+    let long: [u8; 128] = arrayify_string(LONG);
+    assert!(all_bytes(&long, 0, b'y'));
+};
@@ -0,0 +1,83 @@
+//! The list of enabled features emitted as `FlashAlgorithmFeatures`.
+
+/// Every feature of the crate. Keep this in sync with `Cargo.toml`.
+const FEATURES: [(&str, bool); 43] = [
+    ("abort", cfg!(feature = "abort")),
+    ("addr64", cfg!(feature = "addr64")),
+    ("blank-check", cfg!(feature = "blank-check")),
+    ("blank-query", cfg!(feature = "blank-query")),
+    ("cmse", cfg!(feature = "cmse")),
+    ("config-region", cfg!(feature = "config-region")),
+    ("counters", cfg!(feature = "counters")),
+    ("debug-dump", cfg!(feature = "debug-dump")),
+    ("description", cfg!(feature = "description")),
+    ("descriptor-header", cfg!(feature = "descriptor-header")),
+    ("erase-chip", cfg!(feature = "erase-chip")),
+    ("feature-manifest", cfg!(feature = "feature-manifest")),
+    ("fp-save", cfg!(feature = "fp-save")),
+    ("geometry-note", cfg!(feature = "geometry-note")),
+    ("init-args", cfg!(feature = "init-args")),
+    ("minimal-descriptor", cfg!(feature = "minimal-descriptor")),
+    ("name-guard", cfg!(feature = "name-guard")),
+    ("page-index", cfg!(feature = "page-index")),
+    ("panic-handler", cfg!(feature = "panic-handler")),
+    ("program-verify", cfg!(feature = "program-verify")),
+    ("read-flash", cfg!(feature = "read-flash")),
+    ("retry", cfg!(feature = "retry")),
+    ("rtt-minimal", cfg!(feature = "rtt-minimal")),
+    ("scratch-buffer", cfg!(feature = "scratch-buffer")),
+    ("sector-size", cfg!(feature = "sector-size")),
+    ("self-test", cfg!(feature = "self-test")),
+    ("stack-guard", cfg!(feature = "stack-guard")),
+    ("standalone", cfg!(feature = "standalone")),
+    ("stateless", cfg!(feature = "stateless")),
+    ("std", cfg!(feature = "std")),
+    ("strict-align", cfg!(feature = "strict-align")),
+    ("strict-clock", cfg!(feature = "strict-clock")),
+    ("strict-geometry", cfg!(feature = "strict-geometry")),
+    ("stub-unsupported", cfg!(feature = "stub-unsupported")),
+    ("timeout-report", cfg!(feature = "timeout-report")),
+    ("typed-args", cfg!(feature = "typed-args")),
+    ("vendor-status", cfg!(feature = "vendor-status")),
+    ("verify", cfg!(feature = "verify")),
+    ("verify-chunked", cfg!(feature = "verify-chunked")),
+    ("verify-crc", cfg!(feature = "verify-crc")),
+    ("verify-diag", cfg!(feature = "verify-diag")),
+    ("verify-in-place", cfg!(feature = "verify-in-place")),
+    ("verify-progress", cfg!(feature = "verify-progress")),
+];
+
+const LEN: usize = {
+    let mut len = 0;
+    let mut i = 0;
+    while i < FEATURES.len() {
+        if FEATURES[i].1 {
+            len += FEATURES[i].0.len() + 1;
+        }
+        i += 1;
+    }
+    len
+};
+
+/// The features this crate was built with, one per line in alphabetical order, each
+/// followed by `\n`.
+pub const FEATURE_MANIFEST: [u8; LEN] = {
+    let mut manifest = [0; LEN];
+    let mut len = 0;
+    let mut i = 0;
+    while i < FEATURES.len() {
+        if FEATURES[i].1 {
+            let name = FEATURES[i].0.as_bytes();
+            let mut j = 0;
+            while j < name.len() {
+                manifest[len] = name[j];
+                len += 1;
+                j += 1;
+            }
+            manifest[len] = b'\n';
+            len += 1;
+        }
+        i += 1;
+    }
+    manifest
+};
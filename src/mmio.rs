@@ -0,0 +1,118 @@
+//! Volatile register access with the barriers flash controllers need.
+//!
+//! Writes to a flash controller can sit in the write buffer of the core for a while, so
+//! a status register read right after starting an operation may still see the old
+//! state. [`write32()`] therefore completes the write with [`dsb()`] before returning.
+//! Reads are never buffered and need no barrier.
+//!
+//! ```ignore
+//! use flash_algorithm::mmio;
+//!
+//! unsafe {
+//!     mmio::write32(FLASH_CR, CR_SER | CR_STRT);
+//!     while mmio::read32(FLASH_SR) & SR_BSY != 0 {}
+//! }
+//! ```
+
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::ErrorCode;
+
+/// Read a 32-bit register.
+///
+/// # Safety
+///
+/// `address` must be a valid, 4-byte aligned register address.
+#[inline(always)]
+pub unsafe fn read32(address: usize) -> u32 {
+    unsafe { read_volatile(address as *const u32) }
+}
+
+/// Write a 32-bit register and wait until the write has completed.
+///
+/// # Safety
+///
+/// `address` must be a valid, 4-byte aligned register address and writing `value` to
+/// it must not break any invariants of the algorithm.
+#[inline(always)]
+pub unsafe fn write32(address: usize, value: u32) {
+    unsafe { write_volatile(address as *mut u32, value) };
+    dsb();
+}
+
+/// Read a 32-bit register, change the value with `f` and write it back.
+///
+/// # Safety
+///
+/// Same as [`read32()`] and [`write32()`]. The read and the write are not atomic.
+#[inline(always)]
+pub unsafe fn modify32(address: usize, f: impl FnOnce(u32) -> u32) {
+    unsafe { write32(address, f(read32(address))) };
+}
+
+/// Data synchronization barrier: wait until all outstanding memory accesses completed.
+#[inline(always)]
+pub fn dsb() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!("dsb sy", options(nostack, preserves_flags))
+    };
+    #[cfg(not(target_arch = "arm"))]
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Instruction synchronization barrier: refetch the instructions after this point, for
+/// example after changing the configuration of the flash interface or a cache.
+#[inline(always)]
+pub fn isb() {
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!("isb sy", options(nostack, preserves_flags))
+    };
+    #[cfg(not(target_arch = "arm"))]
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// `Ok(())` if none of the bits in `error_mask` are set in `status`, `Err(code)` if any
+/// is, for turning a status register into a result.
+///
+/// ```ignore
+/// let status = unsafe { mmio::read32(FLASH_SR) };
+/// mmio::status_to_result(status, SR_PGERR | SR_WRPRTERR, ERR_PROGRAM)?;
+/// ```
+///
+/// Bits outside of the mask, like a busy or end of operation flag, are ignored. To return
+/// the error bits themselves as the code, use `ErrorCode::new(status & error_mask)`.
+#[inline(always)]
+pub const fn status_to_result(
+    status: u32,
+    error_mask: u32,
+    code: ErrorCode,
+) -> Result<(), ErrorCode> {
+    match status & error_mask {
+        0 => Ok(()),
+        _ => Err(code),
+    }
+}
+
+const fn is(result: Result<(), ErrorCode>, expected: Option<u32>) -> bool {
+    match (result, expected) {
+        (Ok(()), None) => true,
+        (Err(e), Some(expected)) => e.get() == expected,
+        _ => false,
+    }
+}
+
+// Any error bit fails with the given code, bits outside of the mask never do, and an
+// empty mask accepts everything.
+const _: () = {
+    let code = crate::ERR_TIMEOUT;
+    assert!(is(status_to_result(0, 0xF2, code), None));
+    assert!(is(status_to_result(1 << 16, 0xF2, code), None));
+    assert!(is(status_to_result(0xFFFF_FF0D, 0xF2, code), None));
+    assert!(is(status_to_result(0x02, 0xF2, code), Some(2)));
+    assert!(is(status_to_result(0x80, 0xF2, code), Some(2)));
+    assert!(is(status_to_result(0xF2 | 1 << 16, 0xF2, code), Some(2)));
+    assert!(is(status_to_result(u32::MAX, 0, code), None));
+    assert!(is(status_to_result(1 << 31, 1 << 31, code), Some(2)));
+};
@@ -0,0 +1,81 @@
+//! Compile time formatting of the `key=value` geometry note.
+
+/// A fixed size text buffer that can be written to in `const` context.
+#[doc(hidden)]
+pub struct Note<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Note<N> {
+    pub const fn empty() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    pub const fn str(mut self, s: &str) -> Self {
+        let s = s.as_bytes();
+        let mut i = 0;
+        while i < s.len() {
+            self = self.byte(s[i]);
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn dec(mut self, value: u64) -> Self {
+        let mut digits = 1;
+        while digits < 20 && value / 10u64.pow(digits) != 0 {
+            digits += 1;
+        }
+        while digits > 0 {
+            digits -= 1;
+            self = self.byte(b'0' + (value / 10u64.pow(digits) % 10) as u8);
+        }
+        self
+    }
+
+    pub const fn hex(mut self, value: u64) -> Self {
+        self = self.str("0x");
+        let mut digits = 1;
+        while digits < 16 && value >> (4 * digits) != 0 {
+            digits += 1;
+        }
+        while digits > 0 {
+            digits -= 1;
+            self = self.byte(b"0123456789abcdef"[(value >> (4 * digits)) as usize & 0xF]);
+        }
+        self
+    }
+
+    /// Append a `key=value` line with a decimal value.
+    pub const fn dec_line(self, key: &str, value: u64) -> Self {
+        self.str(key).str("=").dec(value).str("\n")
+    }
+
+    /// Append a `key=value` line with a hexadecimal value.
+    pub const fn hex_line(self, key: &str, value: u64) -> Self {
+        self.str(key).str("=").hex(value).str("\n")
+    }
+
+    const fn byte(mut self, byte: u8) -> Self {
+        assert!(self.len < N, "geometry note does not fit in its buffer");
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        self
+    }
+
+    /// The text, padded with NUL bytes.
+    pub const fn finish(self) -> [u8; N] {
+        self.bytes
+    }
+}
+
+/// The buffer size needed for a note with a name of `name_len` bytes and `sectors` sectors.
+#[doc(hidden)]
+pub const fn note_len(name_len: usize, sectors: usize) -> usize {
+    // Every fixed line fits in 32 bytes and every sector line in 48.
+    8 * 32 + name_len + 48 * sectors
+}
@@ -0,0 +1,137 @@
+//! Program data split at page boundaries.
+
+use crate::Addr;
+
+/// Iterator over the parts of `data`, to be programmed at `address`, that fall into one
+/// page each, for hosts that send more than a page to `ProgramPage` at once.
+///
+/// Every item is the address of a part and the bytes that go there. Only the first part
+/// can start inside a page and only the last one can end inside a page; the other parts
+/// are whole pages. A controller that can only program whole pages has to pad the
+/// partial ones with the erased value, so the bytes around the data are left unchanged,
+/// which [`Pages::next_padded()`] does:
+///
+/// ```ignore
+/// let mut parts = pages(address, data, PAGE_SIZE);
+/// while let Some(page_address) = parts.next_padded(&mut self.page_buffer, 0xFF) {
+///     self.write_page_buffer(page_address)?;
+/// }
+/// ```
+///
+/// Iteration stops at the end of the address space, data past it is left out.
+///
+/// `page_size` must not be 0.
+pub fn pages(address: Addr, data: &[u8], page_size: u32) -> Pages<'_> {
+    assert!(page_size != 0);
+    Pages {
+        address,
+        data,
+        page_size,
+    }
+}
+
+/// The iterator returned by [`pages()`].
+pub struct Pages<'a> {
+    address: Addr,
+    data: &'a [u8],
+    page_size: u32,
+}
+
+impl<'a> Pages<'a> {
+    /// Copy the next part into `page` at its offset within its page, with the rest of
+    /// `page` set to `erased`, and return the address where the page starts.
+    ///
+    /// Only the first `page_size` bytes of `page` are used, it must not be shorter.
+    pub fn next_padded(&mut self, page: &mut [u8], erased: u8) -> Option<Addr> {
+        let (address, part) = self.next_part()?;
+        let offset = (address % self.page_size as Addr) as usize;
+        pad(&mut page[..self.page_size as usize], offset, part, erased);
+        Some(address - offset as Addr)
+    }
+
+    const fn next_part(&mut self) -> Option<(Addr, &'a [u8])> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let len = part_len(self.address, self.data.len(), self.page_size);
+        let (part, rest) = self.data.split_at(len);
+        let address = self.address;
+        self.data = match address.checked_add(len as Addr) {
+            Some(next) => {
+                self.address = next;
+                rest
+            }
+            // The part ends at the top of the address space, nothing can follow it.
+            None => &[],
+        };
+        Some((address, part))
+    }
+}
+
+impl<'a> Iterator for Pages<'a> {
+    type Item = (Addr, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_part()
+    }
+}
+
+/// Fill `page` with `erased` and copy `part` into it at `offset`.
+const fn pad(page: &mut [u8], offset: usize, part: &[u8], erased: u8) {
+    let mut i = 0;
+    while i < page.len() {
+        page[i] = if i >= offset && i - offset < part.len() {
+            part[i - offset]
+        } else {
+            erased
+        };
+        i += 1;
+    }
+}
+
+/// The number of the `len` bytes at `address` that are in the same page as `address`.
+const fn part_len(address: Addr, len: usize, page_size: u32) -> usize {
+    let left = (page_size as Addr - address % page_size as Addr) as usize;
+    if len < left {
+        len
+    } else {
+        left
+    }
+}
+
+// An unaligned start is cut at the next boundary, an aligned one takes a whole page, and
+// what is left at the end is returned as it is.
+const _: () = {
+    assert!(part_len(0x1000, 0x300, 0x100) == 0x100);
+    assert!(part_len(0x1010, 0x300, 0x100) == 0xF0);
+    assert!(part_len(0x10FF, 0x300, 0x100) == 1);
+    assert!(part_len(0x1000, 0x20, 0x100) == 0x20);
+    assert!(part_len(0x1010, 0x20, 0x100) == 0x20);
+    assert!(part_len(0x10F0, 0x10, 0x100) == 0x10);
+    assert!(part_len(0x1000, 0x300, 0x180) == 0x80);
+};
+
+// Partial parts are padded with the erased value on both sides, and a region that ends
+// at the top of the address space ends the iteration instead of wrapping around.
+const _: () = {
+    let mut page = [0; 4];
+    pad(&mut page, 1, &[1, 2], 0xFF);
+    assert!(page[0] == 0xFF && page[1] == 1 && page[2] == 2 && page[3] == 0xFF);
+    pad(&mut page, 0, &[3, 4, 5, 6], 0xFF);
+    assert!(page[0] == 3 && page[1] == 4 && page[2] == 5 && page[3] == 6);
+    pad(&mut page, 3, &[7], 0x00);
+    assert!(page[0] == 0 && page[1] == 0 && page[2] == 0 && page[3] == 7);
+
+    let data = [0; 0x30];
+    let mut parts = Pages {
+        address: Addr::MAX - 0x1F,
+        data: &data,
+        page_size: 0x10,
+    };
+    const fn is(part: Option<(Addr, &[u8])>, address: Addr, len: usize) -> bool {
+        matches!(part, Some((a, part)) if a == address && part.len() == len)
+    }
+    assert!(is(parts.next_part(), Addr::MAX - 0x1F, 0x10));
+    assert!(is(parts.next_part(), Addr::MAX - 0xF, 0x10));
+    assert!(parts.next_part().is_none());
+};
@@ -0,0 +1,147 @@
+//! A fixed size byte queue for staging program data.
+
+/// A queue of up to `N` bytes, for staging program data between
+/// [`FlashAlgorithm::program_page()`](crate::FlashAlgorithm::program_page) and a
+/// controller that takes it through a small FIFO.
+///
+/// It is index based and needs `&mut self` for every change, so it is only meant to be
+/// used from the algorithm itself, not shared with an interrupt handler.
+///
+/// ```ignore
+/// let mut data = data;
+/// while !data.is_empty() || !self.staged.is_empty() {
+///     data = &data[self.staged.write(data)..];
+///     while fifo_has_space() {
+///         match self.staged.pop() {
+///             Some(byte) => fifo_push(byte),
+///             None => break,
+///         }
+///     }
+/// }
+/// ```
+pub struct RingBuffer<const N: usize> {
+    buffer: [u8; N],
+    /// Where the oldest byte is.
+    read: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    /// An empty buffer. `N` must not be 0.
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "a `RingBuffer` needs room for at least one byte") };
+        Self {
+            buffer: [0; N],
+            read: 0,
+            len: 0,
+        }
+    }
+
+    /// How many bytes the buffer holds at most.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// How many bytes are queued.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Queue `byte`, or return `false` if the buffer is full.
+    pub const fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.buffer[(self.read + self.len) % N] = byte;
+        self.len += 1;
+        true
+    }
+
+    /// Take the oldest byte, or `None` if the buffer is empty.
+    pub const fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let byte = self.buffer[self.read];
+        self.read = (self.read + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// Queue as many of `bytes` as fit and return how many that were.
+    pub const fn write(&mut self, bytes: &[u8]) -> usize {
+        let mut i = 0;
+        while i < bytes.len() && self.push(bytes[i]) {
+            i += 1;
+        }
+        i
+    }
+
+    /// Take as many bytes as are queued and fit into `bytes`, oldest first, and return
+    /// how many that were.
+    pub const fn read(&mut self, bytes: &mut [u8]) -> usize {
+        let mut i = 0;
+        while i < bytes.len() {
+            match self.pop() {
+                Some(byte) => bytes[i] = byte,
+                None => break,
+            }
+            i += 1;
+        }
+        i
+    }
+
+    /// Drop all queued bytes.
+    pub const fn clear(&mut self) {
+        self.read = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Empty and full are told apart, a full buffer rejects more bytes, and reads and writes
+// that wrap around the end of the storage keep the order of the bytes.
+const _: () = {
+    let mut ring = RingBuffer::<4>::new();
+    assert!(ring.is_empty() && !ring.is_full());
+    assert!(ring.pop().is_none());
+    assert!(ring.write(&[1, 2, 3, 4, 5]) == 4);
+    assert!(ring.is_full() && ring.len() == 4);
+    assert!(!ring.push(6));
+
+    let mut out = [0; 3];
+    assert!(ring.read(&mut out) == 3);
+    assert!(out[0] == 1 && out[1] == 2 && out[2] == 3);
+    // Now starting at index 3, so these wrap around.
+    assert!(ring.write(&[5, 6, 7]) == 3);
+    assert!(ring.is_full());
+    let mut out = [0; 8];
+    assert!(ring.read(&mut out) == 4);
+    assert!(out[0] == 4 && out[1] == 5 && out[2] == 6 && out[3] == 7);
+    assert!(ring.is_empty() && !ring.is_full());
+    assert!(ring.read(&mut out) == 0);
+
+    assert!(ring.push(8));
+    ring.clear();
+    assert!(ring.is_empty());
+    assert!(ring.pop().is_none());
+
+    let mut one = RingBuffer::<1>::new();
+    assert!(one.push(9) && one.is_full() && !one.push(10));
+    assert!(matches!(one.pop(), Some(9)));
+    assert!(one.push(11));
+    assert!(matches!(one.pop(), Some(11)));
+};
@@ -0,0 +1,201 @@
+//! A minimal SEGGER RTT implementation with a single up channel.
+//!
+//! The control block is set up on first use rather than statically because it
+//! contains pointers, and the algorithm may be loaded at any address. Writes never
+//! block: bytes that do not fit into the buffer because the host is not reading
+//! fast enough are dropped.
+
+use core::cell::UnsafeCell;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::Addr;
+
+const BUFFER_SIZE: usize = 512;
+
+/// `SEGGER_RTT_MODE_NO_BLOCK_SKIP`.
+const MODE_NO_BLOCK_SKIP: u32 = 0;
+
+#[repr(C)]
+struct Channel {
+    name: *const u8,
+    buffer: *mut u8,
+    size: u32,
+    write: u32,
+    read: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct ControlBlock {
+    id: [u8; 16],
+    max_up_buffers: i32,
+    max_down_buffers: i32,
+    up: Channel,
+}
+
+struct Rtt {
+    control_block: UnsafeCell<ControlBlock>,
+    buffer: UnsafeCell<[u8; BUFFER_SIZE]>,
+}
+
+unsafe impl Sync for Rtt {}
+
+#[no_mangle]
+#[used]
+static _SEGGER_RTT: Rtt = Rtt {
+    control_block: UnsafeCell::new(ControlBlock {
+        id: [0; 16],
+        max_up_buffers: 0,
+        max_down_buffers: 0,
+        up: Channel {
+            name: core::ptr::null(),
+            buffer: core::ptr::null_mut(),
+            size: 0,
+            write: 0,
+            read: 0,
+            flags: 0,
+        },
+    }),
+    buffer: UnsafeCell::new([0; BUFFER_SIZE]),
+};
+
+/// Set up the control block if this is the first use.
+fn control_block() -> *mut ControlBlock {
+    let cb = _SEGGER_RTT.control_block.get();
+    unsafe {
+        if (*cb).id[0] == 0 {
+            let up = addr_of_mut!((*cb).up);
+            up.write(Channel {
+                name: c"Terminal".as_ptr().cast(),
+                buffer: _SEGGER_RTT.buffer.get().cast(),
+                size: BUFFER_SIZE as u32,
+                write: 0,
+                read: 0,
+                flags: MODE_NO_BLOCK_SKIP,
+            });
+            addr_of_mut!((*cb).max_up_buffers).write(1);
+            addr_of_mut!((*cb).max_down_buffers).write(0);
+            // The host looks for the ID, so it must only appear once the rest is valid.
+            compiler_fence(Ordering::SeqCst);
+            addr_of_mut!((*cb).id).write_volatile(*b"SEGGER RTT\0\0\0\0\0\0");
+        }
+    }
+    cb
+}
+
+/// Write bytes to RTT up channel 0, dropping whatever does not fit.
+pub fn log_bytes(bytes: &[u8]) {
+    let cb = control_block();
+    unsafe {
+        let up = addr_of_mut!((*cb).up);
+        let buffer = (*up).buffer;
+        let read = addr_of!((*up).read).read_volatile() as usize;
+        let mut write = (*up).write as usize;
+        for byte in bytes {
+            let next = (write + 1) % BUFFER_SIZE;
+            if next == read {
+                break;
+            }
+            buffer.add(write).write_volatile(*byte);
+            write = next;
+        }
+        compiler_fence(Ordering::SeqCst);
+        addr_of_mut!((*up).write).write_volatile(write as u32);
+    }
+}
+
+/// How many bytes [`hexdump()`] puts on a line.
+const BYTES_PER_LINE: usize = 16;
+
+/// The longest line [`hexdump()`] writes: the address, a colon, the bytes each with a
+/// space in front, and the newline.
+const LINE_LEN: usize = 2 * core::mem::size_of::<Addr>() + 1 + 3 * BYTES_PER_LINE + 1;
+
+/// Write `bytes`, which were read from `address`, to RTT up channel 0 as hex, 16 bytes
+/// per line after the address of the first one:
+///
+/// ```text
+/// 2001c000: 00 11 22 33 44 55 66 77 88 99 aa bb cc dd ee ff
+/// 2001c010: 5a a5
+/// ```
+///
+/// Nothing is formatted with `core::fmt`, so this is cheap enough to dump a mismatching
+/// region from [`FlashAlgorithm::verify()`](crate::FlashAlgorithm::verify).
+/// Addresses past the top of the address space wrap around to 0.
+pub fn hexdump(address: Addr, bytes: &[u8]) {
+    let mut line = [0; LINE_LEN];
+    for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let address = address.wrapping_add((i * BYTES_PER_LINE) as Addr);
+        let len = format_line(address, chunk, &mut line);
+        log_bytes(&line[..len]);
+    }
+}
+
+/// Format one line of [`hexdump()`] into `line` and return its length. Only the first
+/// [`BYTES_PER_LINE`] of `bytes` are used.
+const fn format_line(address: Addr, bytes: &[u8], line: &mut [u8; LINE_LEN]) -> usize {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    const ADDRESS_DIGITS: usize = 2 * core::mem::size_of::<Addr>();
+    // Every index below is computed from the loop counters, so the compiler can tell that
+    // it is in bounds and leaves out the panics, whose messages are not position
+    // independent.
+    let mut i = 0;
+    while i < ADDRESS_DIGITS {
+        line[i] = DIGITS[(address >> (4 * (ADDRESS_DIGITS - 1 - i))) as usize & 0xF];
+        i += 1;
+    }
+    line[ADDRESS_DIGITS] = b':';
+    let mut i = 0;
+    while i < bytes.len() && i < BYTES_PER_LINE {
+        let at = ADDRESS_DIGITS + 1 + 3 * i;
+        line[at] = b' ';
+        line[at + 1] = DIGITS[(bytes[i] >> 4) as usize];
+        line[at + 2] = DIGITS[(bytes[i] & 0xF) as usize];
+        i += 1;
+    }
+    let end = ADDRESS_DIGITS + 1 + 3 * i;
+    line[end] = b'\n';
+    end + 1
+}
+
+const fn formats_as(address: Addr, bytes: &[u8], expected: &[u8]) -> bool {
+    let mut line = [0; LINE_LEN];
+    let len = format_line(address, bytes, &mut line);
+    if len != expected.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < len {
+        if line[i] != expected[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// The address keeps its leading zeros, a short line ends after its last byte, and bytes
+// past the first 16 are left for the next line.
+#[cfg(not(feature = "addr64"))]
+const _: () = {
+    assert!(formats_as(
+        0x2001_C000,
+        &[0x00, 0x5A, 0xFF],
+        b"2001c000: 00 5a ff\n"
+    ));
+    assert!(formats_as(0x10, &[], b"00000010:\n"));
+    assert!(formats_as(
+        0xFFFF_FFF0,
+        &[0xA5; 17],
+        b"fffffff0: a5 a5 a5 a5 a5 a5 a5 a5 a5 a5 a5 a5 a5 a5 a5 a5\n"
+    ));
+};
+#[cfg(feature = "addr64")]
+const _: () = {
+    assert!(formats_as(
+        0x1_2001_C000,
+        &[0x00, 0x5A, 0xFF],
+        b"000000012001c000: 00 5a ff\n"
+    ));
+};
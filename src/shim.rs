@@ -0,0 +1,1071 @@
+//! The parts of the entry points that depend on the enabled features.
+//!
+//! The entry points are generated in the algorithm crate, where the features of this
+//! crate cannot be tested with `#[cfg]`, so they call into these functions instead.
+
+use core::ops::Range;
+
+use crate::{Addr, ErrorCode, FlashAlgorithm, Function};
+
+/// What the entry points need to know about the declared flash.
+///
+/// Everything is stored inline, a reference would need an absolute relocation.
+pub struct Geometry<const N: usize> {
+    pub flash_address: Addr,
+    pub flash_size: Addr,
+    pub page_size: u32,
+    pub empty_value: u8,
+    /// The `erase_time_out` of the descriptor, for addresses outside of the sectors.
+    pub erase_time_out: u32,
+    /// The option byte region, empty if none was declared.
+    pub config_region: Range<Addr>,
+    /// The declared sectors, without the terminating entry.
+    pub sectors: [Sector; N],
+    pub min_clock: u32,
+    pub max_clock: u32,
+    pub write_align: u32,
+    pub erase_align: u32,
+    pub min_program_size: u32,
+    pub min_erase_size: u32,
+    /// How many times a page is programmed again after a transient error, with `retry`.
+    pub program_retries: u32,
+}
+
+/// A run of equally sized sectors, as in the descriptor.
+pub struct Sector {
+    pub size: Addr,
+    /// Offset of the first sector from `flash_address`.
+    pub address: Addr,
+    /// Whether `EraseChip` leaves these sectors alone.
+    pub preserve: bool,
+    /// The value of an erased byte in these sectors.
+    pub erased: u8,
+    /// How long erasing one of these sectors may take in milliseconds.
+    pub erase_time_out: u32,
+}
+
+impl<const N: usize> Geometry<N> {
+    /// Check that the sectors cover the flash exactly, with the `strict-geometry` feature.
+    ///
+    /// Each entry describes a run of sectors up to the next entry or, for the last one,
+    /// up to `flash_size`, so every run has to hold a whole number of sectors.
+    ///
+    /// Also rejects fields that make an enabled feature do nothing.
+    pub const fn check(&self) {
+        if cfg!(feature = "strict-clock") {
+            assert!(
+                self.min_clock != 0 || self.max_clock != u32::MAX,
+                "`strict-clock` requires a `min_clock` or a `max_clock`"
+            );
+        }
+        if cfg!(feature = "page-index") {
+            assert!(
+                self.page_size != 0,
+                "`page-index` requires a `page_size` larger than 0"
+            );
+        }
+        if cfg!(feature = "retry") {
+            assert!(
+                self.program_retries != 0,
+                "`retry` requires `program_retries` of at least 1"
+            );
+        }
+        if !cfg!(feature = "erase-chip") {
+            let mut i = 0;
+            while i < N {
+                assert!(
+                    !self.sectors[i].preserve,
+                    "`preserve` requires the `erase-chip` feature"
+                );
+                i += 1;
+            }
+        }
+        // Erasing the last sector before a preserved run, or the first one after it, must
+        // not erase a block that reaches into the run.
+        let mut i = 0;
+        while i < N {
+            let end = match i + 1 < N {
+                true => self.sectors[i + 1].address,
+                false => self.flash_size,
+            };
+            let block = self.min_erase_size as Addr;
+            assert!(
+                !self.sectors[i].preserve
+                    || (self.sectors[i].address.is_multiple_of(block) && end.is_multiple_of(block)),
+                "a `preserve` run does not start and end on a `min_erase_size` boundary"
+            );
+            i += 1;
+        }
+        // Hosts that do not know `SectorEraseTimeouts` only wait `erase_time_out`.
+        let mut i = 0;
+        while i < N {
+            assert!(
+                self.sectors[i].erase_time_out <= self.erase_time_out,
+                "the `erase_time_out` of a sector is larger than `erase_time_out`"
+            );
+            i += 1;
+        }
+        // Everything that walks the runs relies on this, see `Sectors`.
+        let mut i = 0;
+        while i + 1 < N {
+            assert!(
+                self.sectors[i + 1].address > self.sectors[i].address,
+                "the sectors are not in ascending order"
+            );
+            i += 1;
+        }
+        if !cfg!(feature = "strict-geometry") {
+            return;
+        }
+        assert!(
+            self.sectors[0].address == 0,
+            "the first sector does not start at `flash_address`"
+        );
+        let mut i = 0;
+        while i + 1 < N {
+            let (sector, next) = (&self.sectors[i], &self.sectors[i + 1]);
+            assert!(sector.size != 0, "a sector has a size of zero");
+            assert!(
+                (next.address - sector.address).is_multiple_of(sector.size),
+                "a run of sectors does not end where the next one starts"
+            );
+            i += 1;
+        }
+        let last = &self.sectors[N - 1];
+        assert!(last.size != 0, "a sector has a size of zero");
+        assert!(
+            self.flash_size > last.address
+                && (self.flash_size - last.address).is_multiple_of(last.size),
+            "the sectors do not add up to `flash_size`"
+        );
+    }
+
+    /// The single sector containing `address`, with its offset from `flash_address`.
+    ///
+    /// `None` outside of the flash, before the first declared sector and in a trailing
+    /// part of a run that is too short for a whole sector.
+    pub const fn sector_at(&self, address: Addr) -> Option<Sector> {
+        let Some(offset) = address.checked_sub(self.flash_address) else {
+            return None;
+        };
+        if offset >= self.flash_size {
+            return None;
+        }
+        let mut i = N;
+        while i > 0 {
+            i -= 1;
+            let run = &self.sectors[i];
+            if run.address > offset {
+                continue;
+            }
+            if run.size == 0 {
+                return None;
+            }
+            let end = match i + 1 < N {
+                true => self.sectors[i + 1].address,
+                false => self.flash_size,
+            };
+            let start = offset - (offset - run.address) % run.size;
+            return match end.checked_sub(start) {
+                Some(room) if room >= run.size => Some(Sector {
+                    size: run.size,
+                    address: start,
+                    preserve: run.preserve,
+                    erased: run.erased,
+                    erase_time_out: run.erase_time_out,
+                }),
+                _ => None,
+            };
+        }
+        None
+    }
+
+    /// The first whole sector that starts at or after `offset` from `flash_address`, or
+    /// `None` if there is none before the end of the flash.
+    pub const fn next_sector(&self, offset: Addr) -> Option<Sector> {
+        let mut i = 0;
+        while i < N {
+            let run = &self.sectors[i];
+            let end = match i + 1 < N {
+                true => self.sectors[i + 1].address,
+                false => self.flash_size,
+            };
+            i += 1;
+            if run.size == 0 || offset >= end {
+                continue;
+            }
+            let start = match offset.checked_sub(run.address) {
+                None | Some(0) => run.address,
+                Some(into) => run.address + into.div_ceil(run.size) * run.size,
+            };
+            if start < end && end - start >= run.size {
+                return Some(Sector {
+                    size: run.size,
+                    address: start,
+                    preserve: run.preserve,
+                    erased: run.erased,
+                    erase_time_out: run.erase_time_out,
+                });
+            }
+        }
+        None
+    }
+
+    /// The address where the sector after the one containing `address` starts, or the
+    /// first sector after `address` if no sector contains it. `None` in and after the
+    /// last sector.
+    pub const fn next_sector_boundary(&self, address: Addr) -> Option<Addr> {
+        let offset = match address.checked_sub(self.flash_address) {
+            Some(offset) if offset >= self.flash_size => return None,
+            Some(offset) => offset + 1,
+            None => 0,
+        };
+        match self.next_sector(offset) {
+            Some(sector) => Some(self.flash_address + sector.address),
+            None => None,
+        }
+    }
+
+    /// Whether all `len` bytes from `address` are in the flash, up to and including its
+    /// last byte.
+    #[allow(clippy::unnecessary_cast)]
+    pub const fn fits(&self, address: Addr, len: usize) -> bool {
+        match address.checked_sub(self.flash_address) {
+            Some(offset) if offset <= self.flash_size => {
+                len as u64 <= (self.flash_size - offset) as u64
+            }
+            _ => false,
+        }
+    }
+
+    /// The value of an erased byte at `address` and the address where the run of
+    /// sectors containing it ends. Outside of the declared runs it is `empty_value`, up
+    /// to the start of the first run or to the end of the address space.
+    pub const fn erased_at(&self, address: Addr) -> (u8, Addr) {
+        let offset = match address.checked_sub(self.flash_address) {
+            Some(offset) if offset < self.flash_size => offset,
+            _ => return (self.empty_value, Addr::MAX),
+        };
+        let mut i = N;
+        while i > 0 {
+            i -= 1;
+            let run = &self.sectors[i];
+            if run.address <= offset {
+                let end = match i + 1 < N {
+                    true => self.sectors[i + 1].address,
+                    false => self.flash_size,
+                };
+                return (run.erased, self.flash_address.saturating_add(end));
+            }
+        }
+        (
+            self.empty_value,
+            self.flash_address.saturating_add(self.sectors[0].address),
+        )
+    }
+
+    /// How long erasing the sector containing `address` may take in milliseconds, the
+    /// `erase_time_out` of the descriptor outside of the declared sectors.
+    pub const fn erase_time_out_at(&self, address: Addr) -> u32 {
+        match self.sector_at(address) {
+            Some(sector) => sector.erase_time_out,
+            None => self.erase_time_out,
+        }
+    }
+
+    #[cfg(feature = "page-index")]
+    #[allow(clippy::unnecessary_cast)]
+    fn page_index(&self, address: Addr) -> u32 {
+        (address.wrapping_sub(self.flash_address) / self.page_size as Addr) as u32
+    }
+
+    #[cfg(feature = "config-region")]
+    fn is_config(&self, address: Addr) -> bool {
+        self.config_region.contains(&address)
+    }
+
+    #[cfg(feature = "strict-geometry")]
+    fn contains(&self, address: Addr) -> bool {
+        address.wrapping_sub(self.flash_address) < self.flash_size
+    }
+
+    #[cfg(feature = "strict-align")]
+    #[allow(clippy::unnecessary_cast)]
+    fn check_align(&self, address: Addr, align: u32) -> Result<(), ErrorCode> {
+        match address
+            .wrapping_sub(self.flash_address)
+            .is_multiple_of(align as Addr)
+        {
+            true => Ok(()),
+            false => Err(crate::ERR_ALIGN),
+        }
+    }
+
+    #[cfg(feature = "strict-align")]
+    fn check_len(&self, data: &[u8]) -> Result<(), ErrorCode> {
+        crate::check_program_len(data.len(), self.min_program_size)
+    }
+
+    /// The size of the sector containing `address`, or [`ERR_ADDRESS`](crate::ERR_ADDRESS)
+    /// if no sector does.
+    #[cfg(feature = "sector-size")]
+    fn sector_size(&self, address: Addr) -> Result<Addr, ErrorCode> {
+        match self.sector_at(address) {
+            Some(sector) => Ok(sector.size),
+            None => Err(crate::ERR_ADDRESS),
+        }
+    }
+}
+
+// Runs of 1 KiB and 4 KiB sectors after a 4 KiB gap, where the flash ends half way
+// into the last 4 KiB sector.
+const _: () = {
+    const GEOMETRY: Geometry<2> = Geometry {
+        flash_address: 0x0800_0000,
+        flash_size: 0x4800,
+        page_size: 0x100,
+        empty_value: 0xFF,
+        erase_time_out: 3000,
+        config_region: 0..0,
+        sectors: [
+            Sector {
+                size: 0x400,
+                address: 0x1000,
+                preserve: false,
+                erased: 0xFF,
+                erase_time_out: 500,
+            },
+            Sector {
+                size: 0x1000,
+                address: 0x2000,
+                preserve: false,
+                erased: 0x00,
+                erase_time_out: 2000,
+            },
+        ],
+        min_clock: 0,
+        max_clock: u32::MAX,
+        write_align: 0x100,
+        erase_align: 0x400,
+        min_program_size: 1,
+        min_erase_size: 0x400,
+        program_retries: 0,
+    };
+    const fn is(address: Addr, expected: Option<(Addr, Addr)>) -> bool {
+        match (GEOMETRY.sector_at(address), expected) {
+            (Some(sector), Some((offset, size))) => sector.address == offset && sector.size == size,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+    assert!(is(0x07FF_FFFF, None));
+    assert!(is(0x0800_0FFF, None));
+    assert!(is(0x0800_1000, Some((0x1000, 0x400))));
+    assert!(is(0x0800_13FF, Some((0x1000, 0x400))));
+    assert!(is(0x0800_1400, Some((0x1400, 0x400))));
+    assert!(is(0x0800_1FFF, Some((0x1C00, 0x400))));
+    assert!(is(0x0800_2000, Some((0x2000, 0x1000))));
+    assert!(is(0x0800_3FFF, Some((0x3000, 0x1000))));
+    assert!(is(0x0800_4000, None));
+    assert!(is(0x0800_4800, None));
+
+    // Each run has its own erased value, up to where the next one starts.
+    const fn erased(address: Addr, expected: (u8, Addr)) -> bool {
+        let (value, end) = GEOMETRY.erased_at(address);
+        value == expected.0 && end == expected.1
+    }
+    assert!(erased(0x0800_0000, (0xFF, 0x0800_1000)));
+    assert!(erased(0x0800_1000, (0xFF, 0x0800_2000)));
+    assert!(erased(0x0800_1FFF, (0xFF, 0x0800_2000)));
+    assert!(erased(0x0800_2000, (0x00, 0x0800_4800)));
+    assert!(erased(0x0800_47FF, (0x00, 0x0800_4800)));
+    assert!(erased(0x0800_4800, (0xFF, Addr::MAX)));
+    assert!(erased(0x07FF_FFFF, (0xFF, Addr::MAX)));
+
+    // Each run has its own erase timeout, outside of the sectors the global one.
+    assert!(GEOMETRY.erase_time_out_at(0x0800_0FFF) == 3000);
+    assert!(GEOMETRY.erase_time_out_at(0x0800_1000) == 500);
+    assert!(GEOMETRY.erase_time_out_at(0x0800_1FFF) == 500);
+    assert!(GEOMETRY.erase_time_out_at(0x0800_2000) == 2000);
+    assert!(GEOMETRY.erase_time_out_at(0x0800_3FFF) == 2000);
+    assert!(GEOMETRY.erase_time_out_at(0x0800_4000) == 3000);
+
+    // Every whole sector once, in ascending order, across the gap, the change of size
+    // and up to the partial sector at the end.
+    const EXPECTED: [(Addr, Addr); 6] = [
+        (0x1000, 0x400),
+        (0x1400, 0x400),
+        (0x1800, 0x400),
+        (0x1C00, 0x400),
+        (0x2000, 0x1000),
+        (0x3000, 0x1000),
+    ];
+    let mut offset = 0;
+    let mut i = 0;
+    while let Some(sector) = GEOMETRY.next_sector(offset) {
+        assert!(i < EXPECTED.len());
+        assert!(sector.address == EXPECTED[i].0 && sector.size == EXPECTED[i].1);
+        offset = sector.address + sector.size;
+        i += 1;
+    }
+    assert!(i == EXPECTED.len());
+    // From inside a sector it is the next one.
+    assert!(matches!(
+        GEOMETRY.next_sector(0x1001),
+        Some(Sector {
+            address: 0x1400,
+            ..
+        })
+    ));
+    assert!(matches!(
+        GEOMETRY.next_sector(0x1C01),
+        Some(Sector {
+            address: 0x2000,
+            ..
+        })
+    ));
+    assert!(GEOMETRY.next_sector(0x3001).is_none());
+
+    // Across the gap, within and between runs, and nothing after the last whole sector.
+    const fn boundary(address: Addr, expected: Option<Addr>) -> bool {
+        match (GEOMETRY.next_sector_boundary(address), expected) {
+            (Some(boundary), Some(expected)) => boundary == expected,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+    assert!(boundary(0x07FF_FFFF, Some(0x0800_1000)));
+    assert!(boundary(0x0800_0000, Some(0x0800_1000)));
+    assert!(boundary(0x0800_0FFF, Some(0x0800_1000)));
+    assert!(boundary(0x0800_1000, Some(0x0800_1400)));
+    assert!(boundary(0x0800_13FF, Some(0x0800_1400)));
+    assert!(boundary(0x0800_1C00, Some(0x0800_2000)));
+    assert!(boundary(0x0800_1FFF, Some(0x0800_2000)));
+    assert!(boundary(0x0800_2000, Some(0x0800_3000)));
+    assert!(boundary(0x0800_2FFF, Some(0x0800_3000)));
+    assert!(boundary(0x0800_3000, None));
+    assert!(boundary(0x0800_3FFF, None));
+    assert!(boundary(0x0800_4000, None));
+    assert!(boundary(0x0800_4800, None));
+    assert!(boundary(Addr::MAX, None));
+
+    // Up to the last byte of the flash, but not one byte further or from before it.
+    assert!(GEOMETRY.fits(0x0800_0000, 0x4800));
+    assert!(!GEOMETRY.fits(0x0800_0000, 0x4801));
+    assert!(GEOMETRY.fits(0x0800_4700, 0x100));
+    assert!(!GEOMETRY.fits(0x0800_4701, 0x100));
+    assert!(GEOMETRY.fits(0x0800_47FF, 1));
+    assert!(!GEOMETRY.fits(0x0800_4800, 1));
+    assert!(GEOMETRY.fits(0x0800_4800, 0));
+    assert!(!GEOMETRY.fits(0x07FF_FFFF, 1));
+    assert!(!GEOMETRY.fits(Addr::MAX, usize::MAX));
+};
+
+/// Iterator over every single sector of a [`Geometry`], in ascending order of address.
+///
+/// The runs are declared in ascending order, which [`Geometry::check()`] enforces, so
+/// this is also the order in which they are declared. Gaps between runs and trailing
+/// parts that are too short for a whole sector are skipped.
+pub struct Sectors<'a, const N: usize> {
+    geometry: &'a Geometry<N>,
+    /// Where to look for the next sector, `None` after the last one.
+    offset: Option<Addr>,
+}
+
+impl<'a, const N: usize> Sectors<'a, N> {
+    pub const fn new(geometry: &'a Geometry<N>) -> Self {
+        Self {
+            geometry,
+            offset: Some(0),
+        }
+    }
+}
+
+impl<const N: usize> Iterator for Sectors<'_, N> {
+    type Item = Sector;
+
+    fn next(&mut self) -> Option<Sector> {
+        let sector = self.geometry.next_sector(self.offset?);
+        self.offset = sector
+            .as_ref()
+            .and_then(|sector| sector.address.checked_add(sector.size));
+        sector
+    }
+}
+
+/// The smallest of the declared sector sizes, the default `erase_align`.
+#[allow(clippy::unnecessary_cast)]
+pub const fn min_sector_size(sizes: &[Addr]) -> u32 {
+    let mut min = Addr::MAX;
+    let mut i = 0;
+    while i < sizes.len() {
+        if sizes[i] < min {
+            min = sizes[i];
+        }
+        i += 1;
+    }
+    min as u32
+}
+
+/// Called by `Init` before the algorithm is created, which it prevents by returning an error.
+#[cfg_attr(
+    not(all(feature = "strict-clock", feature = "strict-geometry")),
+    allow(unused_variables)
+)]
+pub fn init<const N: usize>(
+    geometry: &Geometry<N>,
+    address: Addr,
+    clock: u32,
+) -> Result<(), ErrorCode> {
+    #[cfg(feature = "strict-geometry")]
+    if !geometry.contains(address) && !geometry.config_region.contains(&address) {
+        return Err(crate::ERR_ADDRESS);
+    }
+    #[cfg(feature = "strict-clock")]
+    if !(geometry.min_clock..=geometry.max_clock).contains(&clock) {
+        return Err(crate::ERR_CLOCK);
+    }
+    #[cfg(feature = "stack-guard")]
+    crate::stack::arm();
+    #[cfg(feature = "verify-chunked")]
+    unsafe {
+        *RUNNING.0.get() = None
+    };
+    #[cfg(feature = "init-args")]
+    unsafe {
+        *INIT_ARGS.0.get() = (address, clock)
+    };
+    Ok(())
+}
+
+/// The `address` and `clock` of the last successful `Init` checks.
+#[cfg(feature = "init-args")]
+struct InitArgs(core::cell::UnsafeCell<(Addr, u32)>);
+
+#[cfg(feature = "init-args")]
+unsafe impl Sync for InitArgs {}
+
+#[cfg(feature = "init-args")]
+static INIT_ARGS: InitArgs = InitArgs(core::cell::UnsafeCell::new((0, 0)));
+
+/// The `address` argument of the last `Init`, as passed to [`FlashAlgorithm::new()`].
+///
+/// With `init-args` the methods of the algorithm can read it here instead of keeping a
+/// copy in their own struct. It is only valid while the algorithm is initialized.
+#[cfg(feature = "init-args")]
+pub fn init_address() -> Addr {
+    // Entry points are never entered concurrently, so nothing writes this meanwhile.
+    unsafe { (*INIT_ARGS.0.get()).0 }
+}
+
+/// The `clock` argument of the last `Init` in Hertz, as passed to
+/// [`FlashAlgorithm::new()`].
+///
+/// With `init-args` the methods of the algorithm can read it here, for example to
+/// recompute controller timings, instead of keeping a copy in their own struct.
+///
+/// ```ignore
+/// fn program_page(&mut self, address: u32, data: &[u8]) -> Result<(), ErrorCode> {
+///     set_wait_states(flash_algorithm::init_clock());
+///     // ..
+/// }
+/// ```
+#[cfg(feature = "init-args")]
+pub fn init_clock() -> u32 {
+    unsafe { (*INIT_ARGS.0.get()).1 }
+}
+
+/// Create the algorithm with the constructor for `function`.
+#[cfg(not(feature = "scratch-buffer"))]
+pub fn new<T: FlashAlgorithm>(
+    address: Addr,
+    clock: u32,
+    function: Function,
+) -> Result<T, ErrorCode> {
+    #[cfg(feature = "typed-args")]
+    let (address, clock) = (crate::Address(address), crate::Clock(clock));
+    let algorithm = match function {
+        Function::Erase => T::new_erase(address, clock),
+        Function::Program => T::new_program(address, clock),
+        Function::Verify => T::new_verify(address, clock),
+        #[cfg(feature = "blank-check")]
+        Function::Blank => T::new(address, clock, function),
+    };
+    self_test(algorithm)
+}
+
+/// Create the algorithm with the constructor for `function`.
+#[cfg(feature = "scratch-buffer")]
+pub fn new<T: FlashAlgorithm>(
+    address: Addr,
+    clock: u32,
+    function: Function,
+    scratch: &'static mut [u8],
+) -> Result<T, ErrorCode> {
+    #[cfg(feature = "typed-args")]
+    let (address, clock) = (crate::Address(address), crate::Clock(clock));
+    let algorithm = match function {
+        Function::Erase => T::new_erase(address, clock, scratch),
+        Function::Program => T::new_program(address, clock, scratch),
+        Function::Verify => T::new_verify(address, clock, scratch),
+        #[cfg(feature = "blank-check")]
+        Function::Blank => T::new(address, clock, function, scratch),
+    };
+    self_test(algorithm)
+}
+
+/// Run the self-test of a new algorithm, dropping it if that fails.
+fn self_test<T: FlashAlgorithm>(algorithm: Result<T, ErrorCode>) -> Result<T, ErrorCode> {
+    #[cfg(feature = "self-test")]
+    let algorithm = algorithm.and_then(|mut algorithm| {
+        algorithm.self_test()?;
+        Ok(algorithm)
+    });
+    algorithm
+}
+
+/// Called by `UnInit` after the algorithm was dropped, returns its result.
+pub fn uninit() -> u32 {
+    #[cfg(feature = "stack-guard")]
+    if let Err(e) = crate::check_stack() {
+        return e.get();
+    }
+    0
+}
+
+pub fn erase_sector<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+) -> Result<(), ErrorCode> {
+    let result = erase_sector_inner(this, geometry, address);
+    #[cfg(feature = "counters")]
+    if result.is_ok() {
+        crate::counters::increment(&crate::COUNTERS.sectors_erased);
+    }
+    result
+}
+
+fn erase_sector_inner<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+) -> Result<(), ErrorCode> {
+    #[cfg(feature = "config-region")]
+    if geometry.is_config(address) {
+        return this.erase_option(address);
+    }
+    // Gaps, a trailing partial sector and anything outside of the flash.
+    #[cfg(feature = "strict-geometry")]
+    if geometry.sector_at(address).is_none() {
+        return Err(crate::ERR_ADDRESS);
+    }
+    // `min_erase_size` is a multiple of `erase_align`.
+    #[cfg(feature = "strict-align")]
+    geometry.check_align(address, geometry.min_erase_size)?;
+    erase_declared_sector(this, geometry, address)
+}
+
+/// Erase the declared sector at `address`, which may be inside of a `min_erase_size`
+/// block.
+#[cfg_attr(not(feature = "sector-size"), allow(unused_variables))]
+fn erase_declared_sector<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+) -> Result<(), ErrorCode> {
+    #[cfg(feature = "sector-size")]
+    return this.erase_sector(address, geometry.sector_size(address)?);
+    #[cfg(not(feature = "sector-size"))]
+    this.erase_sector(address)
+}
+
+pub fn program_page<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+    data: &[u8],
+) -> Result<(), ErrorCode> {
+    #[cfg(not(feature = "retry"))]
+    let result = program_page_inner(this, geometry, address, data);
+    #[cfg(feature = "retry")]
+    let result = {
+        let mut result = program_page_inner(this, geometry, address, data);
+        let mut retries = 0;
+        while let Err(e) = result {
+            if retries == geometry.program_retries || !this.is_transient(e) {
+                break;
+            }
+            retries += 1;
+            #[cfg(feature = "counters")]
+            crate::count_retry();
+            result = program_page_inner(this, geometry, address, data);
+        }
+        result
+    };
+    #[cfg(feature = "counters")]
+    if result.is_ok() {
+        crate::counters::increment(&crate::COUNTERS.pages_programmed);
+    }
+    result
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "config-region",
+        feature = "page-index",
+        feature = "strict-align",
+        feature = "strict-geometry"
+    )),
+    allow(unused_variables)
+)]
+fn program_page_inner<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+    data: &[u8],
+) -> Result<(), ErrorCode> {
+    #[cfg(feature = "config-region")]
+    if geometry.is_config(address) {
+        return this.program_option(address, data);
+    }
+    #[cfg(feature = "strict-geometry")]
+    if !geometry.fits(address, data.len()) {
+        return Err(crate::ERR_OUT_OF_BOUNDS);
+    }
+    #[cfg(feature = "strict-align")]
+    {
+        geometry.check_align(address, geometry.write_align)?;
+        geometry.check_len(data)?;
+    }
+    #[cfg(feature = "page-index")]
+    return this.program_page(address, geometry.page_index(address), data);
+    #[cfg(not(feature = "page-index"))]
+    this.program_page(address, data)
+}
+
+#[cfg(feature = "program-verify")]
+pub fn program_and_verify<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+    data: &[u8],
+) -> Result<(), ErrorCode> {
+    let result = program_and_verify_inner(this, geometry, address, data);
+    #[cfg(feature = "counters")]
+    if result.is_ok() {
+        crate::counters::increment(&crate::COUNTERS.pages_programmed);
+    }
+    result
+}
+
+#[cfg(feature = "program-verify")]
+#[cfg_attr(
+    not(any(
+        feature = "config-region",
+        feature = "page-index",
+        feature = "strict-align",
+        feature = "strict-geometry"
+    )),
+    allow(unused_variables)
+)]
+fn program_and_verify_inner<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+    data: &[u8],
+) -> Result<(), ErrorCode> {
+    #[cfg(feature = "verify-diag")]
+    reset_mismatches();
+    #[cfg(feature = "config-region")]
+    if geometry.is_config(address) {
+        this.program_option(address, data)?;
+        return verify_exact(this, address, data.len() as u32, Some(data));
+    }
+    #[cfg(feature = "strict-geometry")]
+    if !geometry.fits(address, data.len()) {
+        return Err(crate::ERR_OUT_OF_BOUNDS);
+    }
+    #[cfg(feature = "strict-align")]
+    {
+        geometry.check_align(address, geometry.write_align)?;
+        geometry.check_len(data)?;
+    }
+    #[cfg(feature = "page-index")]
+    return this.program_and_verify(address, geometry.page_index(address), data);
+    #[cfg(not(feature = "page-index"))]
+    this.program_and_verify(address, data)
+}
+
+#[cfg(feature = "erase-chip")]
+pub fn erase_all<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+) -> Result<(), ErrorCode> {
+    let result = match geometry.sectors.iter().any(|run| run.preserve) {
+        true => erase_unpreserved(this, geometry),
+        false => this.erase_all(),
+    };
+    #[cfg(feature = "counters")]
+    if result.is_ok() {
+        crate::counters::increment(&crate::COUNTERS.chip_erases);
+    }
+    result
+}
+
+/// Erase every sector that is not marked with `preserve`, in place of a bulk erase.
+///
+/// Sectors smaller than `min_erase_size` are erased one by one even though each erases
+/// its whole block: `Geometry::check` keeps preserved runs out of those blocks, and this
+/// does not count them in `sectors_erased`.
+#[cfg(feature = "erase-chip")]
+fn erase_unpreserved<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+) -> Result<(), ErrorCode> {
+    for sector in Sectors::new(geometry).filter(|sector| !sector.preserve) {
+        erase_declared_sector(this, geometry, geometry.flash_address + sector.address)?;
+    }
+    Ok(())
+}
+
+/// Forget the mismatches recorded by the previous verification.
+#[cfg(feature = "verify-diag")]
+fn reset_mismatches() {
+    crate::VERIFY_MISMATCH_COUNT.store(0, core::sync::atomic::Ordering::Relaxed);
+}
+
+#[cfg(all(feature = "verify", not(feature = "verify-progress")))]
+pub fn verify<T: FlashAlgorithm>(this: &mut T, address: Addr, data: &[u8]) -> u32 {
+    #[cfg(feature = "verify-diag")]
+    reset_mismatches();
+    match this.verify(address, data.len() as u32, Some(data)) {
+        Ok(()) => 0,
+        Err(e) => e.get(),
+    }
+}
+#[cfg(feature = "verify-progress")]
+pub fn verify<T: FlashAlgorithm>(this: &mut T, address: Addr, data: &[u8]) -> u32 {
+    #[cfg(feature = "verify-diag")]
+    reset_mismatches();
+    set_verify_status(0);
+    let size = data.len() as u32;
+    let verified = match this.verify(address, size, Some(data)) {
+        Ok(count) => count,
+        Err((offset, e)) => {
+            set_verify_status(e.get());
+            offset
+        }
+    };
+    address.wrapping_add(verified.min(size))
+}
+
+#[cfg(feature = "verify-progress")]
+fn set_verify_status(status: u32) {
+    crate::VERIFY_STATUS.store(status, core::sync::atomic::Ordering::Relaxed);
+}
+
+/// Verify `size` bytes at `address` against `data`, or that they are erased for `None`,
+/// for the callers that only need to know whether they match.
+#[cfg(all(feature = "verify", not(feature = "verify-progress")))]
+pub fn verify_exact<T: FlashAlgorithm>(
+    this: &mut T,
+    address: Addr,
+    size: u32,
+    data: Option<&[u8]>,
+) -> Result<(), ErrorCode> {
+    this.verify(address, size, data)
+}
+#[cfg(feature = "verify-progress")]
+pub fn verify_exact<T: FlashAlgorithm>(
+    this: &mut T,
+    address: Addr,
+    size: u32,
+    data: Option<&[u8]>,
+) -> Result<(), ErrorCode> {
+    match this.verify(address, size, data) {
+        Ok(count) if count >= size => Ok(()),
+        Ok(_) => Err(crate::ERR_VERIFY_INCOMPLETE),
+        Err((_, e)) => Err(e),
+    }
+}
+
+#[cfg(feature = "verify-in-place")]
+#[cfg_attr(not(feature = "strict-geometry"), allow(unused_variables))]
+pub fn verify_in_place<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+    data: &[u8],
+) -> Result<(), ErrorCode> {
+    #[cfg(feature = "strict-geometry")]
+    if !geometry.fits(address, data.len()) {
+        return Err(crate::ERR_OUT_OF_BOUNDS);
+    }
+    #[cfg(feature = "verify-diag")]
+    reset_mismatches();
+    this.verify_in_place(address, data)
+}
+
+#[cfg(all(
+    feature = "verify",
+    not(feature = "verify-crc"),
+    not(feature = "verify-progress")
+))]
+pub fn verify_without_data<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+    size: u32,
+) -> u32 {
+    #[cfg(feature = "verify-diag")]
+    reset_mismatches();
+    match verify_erased(this, geometry, address, size) {
+        Ok(()) => 0,
+        Err(e) => e.get(),
+    }
+}
+#[cfg(all(feature = "verify-progress", not(feature = "verify-crc")))]
+pub fn verify_without_data<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    address: Addr,
+    size: u32,
+) -> u32 {
+    #[cfg(feature = "verify-diag")]
+    reset_mismatches();
+    set_verify_status(0);
+    match verify_erased(this, geometry, address, size) {
+        Ok(()) => address.wrapping_add(size),
+        Err(e) => {
+            set_verify_status(e.get());
+            address
+        }
+    }
+}
+
+/// Check a region that may span runs of sectors with different erased values, one run
+/// at a time.
+#[cfg(all(feature = "verify", not(feature = "verify-crc")))]
+#[allow(clippy::unnecessary_cast)]
+fn verify_erased<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    mut address: Addr,
+    size: u32,
+) -> Result<(), ErrorCode> {
+    let end = address.saturating_add(size as Addr);
+    loop {
+        let (erased, run_end) = geometry.erased_at(address);
+        // Sectors that are not in order would not end after `address`.
+        let run_end = if run_end > address { run_end } else { end };
+        let len = run_end.min(end) - address;
+        this.verify_erased(address, len as u32, erased)?;
+        address += len;
+        if address >= end {
+            return Ok(());
+        }
+    }
+}
+#[cfg(feature = "blank-query")]
+#[cfg_attr(not(feature = "strict-geometry"), allow(unused_variables))]
+#[allow(clippy::unnecessary_cast)]
+pub fn is_range_blank<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    geometry: &Geometry<N>,
+    mut address: Addr,
+    size: u32,
+) -> Result<bool, ErrorCode> {
+    #[cfg(feature = "strict-geometry")]
+    if !geometry.fits(address, size as usize) {
+        return Err(crate::ERR_OUT_OF_BOUNDS);
+    }
+    let end = address.saturating_add(size as Addr);
+    loop {
+        let (erased, run_end) = geometry.erased_at(address);
+        // Sectors that are not in order would not end after `address`.
+        let run_end = if run_end > address { run_end } else { end };
+        let len = run_end.min(end) - address;
+        if !this.is_range_blank(address, len as u32, erased)? {
+            return Ok(false);
+        }
+        address += len;
+        if address >= end {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(all(feature = "verify-crc", not(feature = "verify-chunked")))]
+pub fn verify_without_data<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    _geometry: &Geometry<N>,
+    address: Addr,
+    size: u32,
+) -> u32 {
+    set_verify_crc(0);
+    let result = this.crc(address, size).map(set_verify_crc);
+    crc_status(address, size, result)
+}
+#[cfg(feature = "verify-chunked")]
+pub fn verify_without_data<T: FlashAlgorithm, const N: usize>(
+    this: &mut T,
+    _geometry: &Geometry<N>,
+    address: Addr,
+    size: u32,
+) -> u32 {
+    // Entry points are never entered concurrently, so nothing else holds this.
+    let running = unsafe { &mut *RUNNING.0.get() };
+    let mut crc = match running.take() {
+        Some((crc, next)) if next == address && size != 0 => crc,
+        _ => crate::Crc32::new(),
+    };
+    set_verify_crc(0);
+    let result = this.crc(&mut crc, address, size).map(|()| {
+        // A chunk that ends at the top of the address space cannot be continued.
+        if size != 0 {
+            *running = address.checked_add(size as Addr).map(|next| (crc, next));
+        }
+        set_verify_crc(crc.finish());
+    });
+    crc_status(address, size, result)
+}
+
+#[cfg(feature = "verify-crc")]
+fn set_verify_crc(crc: u32) {
+    crate::VERIFY_CRC.store(crc, core::sync::atomic::Ordering::Relaxed);
+}
+
+/// What `Verify` without data returns for the result of computing the checksum, which
+/// is already in `VERIFY_CRC`.
+#[cfg(feature = "verify-crc")]
+#[cfg_attr(not(feature = "verify-progress"), allow(unused_variables))]
+fn crc_status(address: Addr, size: u32, result: Result<(), ErrorCode>) -> u32 {
+    #[cfg(not(feature = "verify-progress"))]
+    return match result {
+        Ok(()) => 0,
+        Err(e) => e.get(),
+    };
+    #[cfg(feature = "verify-progress")]
+    match result {
+        Ok(()) => {
+            set_verify_status(0);
+            address.wrapping_add(size)
+        }
+        Err(e) => {
+            set_verify_status(e.get());
+            address
+        }
+    }
+}
+
+/// The checksum of the current run of `Verify` chunks and where the next chunk has to
+/// start to continue it.
+#[cfg(feature = "verify-chunked")]
+struct Running(core::cell::UnsafeCell<Option<(crate::Crc32, Addr)>>);
+
+#[cfg(feature = "verify-chunked")]
+unsafe impl Sync for Running {}
+
+#[cfg(feature = "verify-chunked")]
+static RUNNING: Running = Running(core::cell::UnsafeCell::new(None));
@@ -0,0 +1,103 @@
+//! Verification that checks erased stretches of the expected data as blank instead of
+//! comparing them byte by byte.
+
+use crate::{Addr, ErrorCode, ERR_OUT_OF_BOUNDS};
+
+/// Verify `data` at `address`, handing stretches of at least `min_run` bytes that are
+/// all `erased` in `data` to `blank` and everything else to `compare`.
+///
+/// Images often have large erased gaps between the parts that hold code and data. A
+/// controller with a hardware blank check, or one that can tell from its own state that
+/// a region was not programmed since the last erase, checks those much faster than
+/// reading and comparing them. `blank` gets the address and length of an erased
+/// stretch, `compare` the address and expected bytes of the rest. The first error
+/// either returns is returned. A stretch that would run past the top of the address
+/// space fails with [`ERR_OUT_OF_BOUNDS`] instead of being handed to either. `min_run`
+/// keeps short erased stretches inside programmed data from being checked separately,
+/// where a call costs more than it saves.
+///
+/// ```ignore
+/// fn verify(&mut self, address: u32, size: u32, data: Option<&[u8]>) -> Result<(), ErrorCode> {
+///     let data = &data.unwrap()[..size as usize];
+///     verify_sparse(
+///         address,
+///         data,
+///         0xFF,
+///         64,
+///         |address, expected| compare_memory_mapped(address, expected),
+///         |address, size| hardware_blank_check(address, size),
+///     )
+/// }
+/// ```
+pub fn verify_sparse(
+    address: Addr,
+    data: &[u8],
+    erased: u8,
+    min_run: usize,
+    mut compare: impl FnMut(Addr, &[u8]) -> Result<(), ErrorCode>,
+    mut blank: impl FnMut(Addr, u32) -> Result<(), ErrorCode>,
+) -> Result<(), ErrorCode> {
+    let mut offset = 0;
+    while offset < data.len() {
+        let rest = &data[offset..];
+        let (len, is_erased) = next_run(rest, erased, min_run);
+        let address = address
+            .checked_add(offset as Addr)
+            .filter(|address| address.checked_add(len as Addr - 1).is_some())
+            .ok_or(ERR_OUT_OF_BOUNDS)?;
+        if is_erased {
+            blank(address, len as u32)?;
+        } else {
+            compare(address, &rest[..len])?;
+        }
+        offset += len;
+    }
+    Ok(())
+}
+
+/// The length of the first stretch of `data` and whether it is an erased one of at
+/// least `min_run` bytes. A stretch that is not erased ends where the next erased one
+/// starts.
+const fn next_run(data: &[u8], erased: u8, min_run: usize) -> (usize, bool) {
+    let min_run = if min_run == 0 { 1 } else { min_run };
+    let mut i = 0;
+    // Where the erased bytes before `i` start.
+    let mut run_start = 0;
+    while i < data.len() {
+        if data[i] != erased {
+            if run_start == 0 && i >= min_run {
+                return (i, true);
+            }
+            run_start = i + 1;
+        } else if run_start > 0 && i + 1 - run_start >= min_run {
+            return (run_start, false);
+        }
+        i += 1;
+    }
+    if run_start == 0 && data.len() >= min_run {
+        (data.len(), true)
+    } else {
+        (data.len(), false)
+    }
+}
+
+const fn is_run(data: &[u8], min_run: usize, expected: (usize, bool)) -> bool {
+    let run = next_run(data, 0xFF, min_run);
+    run.0 == expected.0 && run.1 == expected.1
+}
+
+// Erased stretches are split off at both ends and in the middle once they are long
+// enough, shorter ones stay part of the data around them.
+const _: () = {
+    const E: u8 = 0xFF;
+    assert!(is_run(&[E, E, E, E, 1, 2], 4, (4, true)));
+    assert!(is_run(&[E, E, E, 1, 2], 4, (5, false)));
+    assert!(is_run(&[1, 2, E, E, E, E, 3], 4, (2, false)));
+    assert!(is_run(&[1, E, E, 2, E, E, E, E], 4, (4, false)));
+    assert!(is_run(&[E, E, E, E], 4, (4, true)));
+    assert!(is_run(&[E, E], 4, (2, false)));
+    assert!(is_run(&[1, 2, 3], 4, (3, false)));
+    assert!(is_run(&[E, 1], 0, (1, true)));
+    assert!(is_run(&[1, E], 1, (1, false)));
+    assert!(is_run(&[], 4, (0, false)));
+};
@@ -0,0 +1,40 @@
+//! A sentinel at the bottom of the stack to detect overflows after the fact.
+//!
+//! The host places the stack of the algorithm directly above the loaded blob, so the
+//! stack grows down towards the end of `PrgCode`. `memory.x` puts [`STACK_GUARD`] last
+//! in `PrgCode`, where an overflowing stack overwrites it before it reaches any of the
+//! algorithm's own data.
+
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+
+use crate::ErrorCode;
+
+const SENTINEL: u32 = 0x5AFE_57AC;
+
+/// The sentinel words, written by `Init` and checked by [`check_stack()`].
+#[link_section = ".stack_guard"]
+#[used]
+static STACK_GUARD: [AtomicU32; 4] = [const { AtomicU32::new(SENTINEL) }; 4];
+
+/// Write the sentinel, so a clobbered guard from an earlier session is not reported again.
+pub(crate) fn arm() {
+    for word in &STACK_GUARD {
+        word.store(SENTINEL, Relaxed);
+    }
+}
+
+/// Check that the stack has not grown past its bottom since `Init`.
+///
+/// Returns [`ERR_STACK_OVERFLOW`](crate::ERR_STACK_OVERFLOW) if the sentinel was
+/// overwritten. `UnInit` calls this after dropping the algorithm; it can also be called
+/// at the end of an operation that is suspected of using too much stack.
+pub fn check_stack() -> Result<(), ErrorCode> {
+    if STACK_GUARD
+        .iter()
+        .all(|word| word.load(Relaxed) == SENTINEL)
+    {
+        Ok(())
+    } else {
+        Err(crate::ERR_STACK_OVERFLOW)
+    }
+}
@@ -0,0 +1,146 @@
+//! A fixed capacity byte vector for coalescing program data.
+
+use crate::{ErrorCode, ERR_LENGTH};
+
+/// Up to `N` bytes collected in order, for an algorithm that has to gather the data of
+/// several calls before it hands a whole controller write unit to the flash.
+///
+/// It never allocates and never panics: adding more than fits fails with
+/// [`ERR_LENGTH`] and leaves the contents as they were.
+///
+/// ```ignore
+/// self.pending.extend_from_slice(data)?;
+/// if self.pending.is_full() {
+///     write_unit(self.pending.as_slice())?;
+///     self.pending.clear();
+/// }
+/// ```
+pub struct StackVec<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StackVec<N> {
+    /// An empty vector.
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+        }
+    }
+
+    /// How many bytes the vector holds at most.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// How many bytes have been added.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// How many more bytes fit.
+    pub const fn remaining(&self) -> usize {
+        N - self.len
+    }
+
+    /// Add `byte` at the end, or return [`ERR_LENGTH`] if the vector is full.
+    pub const fn push(&mut self, byte: u8) -> Result<(), ErrorCode> {
+        if self.is_full() {
+            return Err(ERR_LENGTH);
+        }
+        self.buffer[self.len] = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Add all of `bytes` at the end, or none of them and return [`ERR_LENGTH`] if they
+    /// do not fit.
+    pub const fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        if bytes.len() > self.remaining() {
+            return Err(ERR_LENGTH);
+        }
+        let mut i = 0;
+        while i < bytes.len() {
+            self.buffer[self.len + i] = bytes[i];
+            i += 1;
+        }
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    /// The bytes added so far.
+    pub const fn as_slice(&self) -> &[u8] {
+        self.buffer.split_at(self.len).0
+    }
+
+    /// The bytes added so far, to patch them in place.
+    pub const fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.split_at_mut(self.len).0
+    }
+
+    /// Remove all bytes.
+    pub const fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for StackVec<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> core::ops::Deref for StackVec<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+// Overflowing pushes and extends fail without changing the contents, an extend that
+// fits exactly fills the vector, and clearing makes room again.
+const _: () = {
+    const fn is(result: Result<(), ErrorCode>, expected: Option<ErrorCode>) -> bool {
+        match (result, expected) {
+            (Ok(()), None) => true,
+            (Err(e), Some(expected)) => e.get() == expected.get(),
+            _ => false,
+        }
+    }
+
+    let mut vec = StackVec::<4>::new();
+    assert!(vec.is_empty() && !vec.is_full() && vec.remaining() == 4);
+    assert!(vec.as_slice().is_empty());
+    assert!(is(vec.push(1), None));
+    assert!(is(vec.extend_from_slice(&[2, 3, 4, 5]), Some(ERR_LENGTH)));
+    assert!(vec.len() == 1 && vec.as_slice()[0] == 1);
+    assert!(is(vec.extend_from_slice(&[2, 3, 4]), None));
+    assert!(vec.is_full() && vec.remaining() == 0);
+    assert!(is(vec.push(5), Some(ERR_LENGTH)));
+    assert!(is(vec.extend_from_slice(&[5]), Some(ERR_LENGTH)));
+    assert!(is(vec.extend_from_slice(&[]), None));
+    let bytes = vec.as_slice();
+    assert!(bytes.len() == 4 && bytes[0] == 1 && bytes[1] == 2 && bytes[3] == 4);
+
+    vec.as_mut_slice()[1] = 6;
+    assert!(vec.as_slice()[1] == 6);
+    vec.clear();
+    assert!(vec.is_empty() && vec.remaining() == 4);
+    assert!(is(vec.push(7), None));
+    assert!(vec.len() == 1 && vec.as_slice()[0] == 7);
+
+    let mut none = StackVec::<0>::new();
+    assert!(none.is_full() && none.is_empty());
+    assert!(is(none.push(0), Some(ERR_LENGTH)));
+    assert!(is(none.extend_from_slice(&[]), None));
+};
@@ -0,0 +1,47 @@
+//! A reset stub for running an algorithm on bare hardware without a host, for bring-up.
+
+#[cfg(any(feature = "std", not(target_arch = "arm")))]
+compile_error!("`standalone` is only for bring-up on Arm Cortex-M hardware");
+
+/// The size of [`StandaloneStack`].
+pub const STANDALONE_STACK_SIZE: usize = 1024;
+
+/// The stack the reset stub runs on, in place of the one a host provides.
+#[doc(hidden)]
+#[repr(C, align(8))]
+pub struct StandaloneStack(core::cell::UnsafeCell<[u8; STANDALONE_STACK_SIZE]>);
+
+unsafe impl Sync for StandaloneStack {}
+
+impl StandaloneStack {
+    pub const fn zeroed() -> Self {
+        Self(core::cell::UnsafeCell::new([0; STANDALONE_STACK_SIZE]))
+    }
+
+    /// The initial stack pointer, the end of the stack.
+    pub const fn top(&'static self) -> *const u8 {
+        self.0
+            .get()
+            .cast::<u8>()
+            .wrapping_add(STANDALONE_STACK_SIZE)
+    }
+}
+
+/// The first two entries of a Cortex-M vector table, enough to boot from reset.
+#[doc(hidden)]
+#[repr(C)]
+pub struct StandaloneVectors {
+    pub stack_top: *const u8,
+    pub reset: unsafe extern "C" fn() -> !,
+}
+
+unsafe impl Sync for StandaloneVectors {}
+
+/// Stop with `status` in `r0`, for the debugger to read once the core halts on the
+/// breakpoint.
+#[doc(hidden)]
+pub fn halt(status: u32) -> ! {
+    loop {
+        unsafe { core::arch::asm!("bkpt #0", in("r0") status, options(nomem, nostack)) };
+    }
+}
@@ -0,0 +1,159 @@
+//! Program data split into words, without any alignment requirements.
+
+use core::marker::PhantomData;
+
+/// An integer type program data can be split into: `u16`, `u32` or `u64`.
+pub trait Word: Copy + private::Sealed {
+    /// Build a word from up to `size_of::<Self>()` little endian bytes. Missing high
+    /// bytes are set to `fill`.
+    fn from_partial_le(bytes: &[u8], fill: u8) -> Self;
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+macro_rules! word {
+    ($($type:ty),+) => {
+        $(
+            impl private::Sealed for $type {}
+
+            impl Word for $type {
+                fn from_partial_le(bytes: &[u8], fill: u8) -> Self {
+                    Self::from_le_bytes(pad(bytes, fill))
+                }
+            }
+        )+
+    };
+}
+
+word!(u16, u32, u64);
+
+/// Copy `bytes` into an array of `N` bytes set to `fill`. Extra bytes are ignored.
+const fn pad<const N: usize>(bytes: &[u8], fill: u8) -> [u8; N] {
+    let mut word = [fill; N];
+    let mut i = 0;
+    while i < N && i < bytes.len() {
+        word[i] = bytes[i];
+        i += 1;
+    }
+    word
+}
+
+// A trailing partial word keeps its bytes at the low end and is padded with the fill byte.
+const _: () = {
+    assert!(u32::from_le_bytes(pad(&[0x11, 0x22, 0x33], 0xFF)) == 0xFF33_2211);
+    assert!(u16::from_le_bytes(pad(&[0x11], 0x00)) == 0x0011);
+    assert!(u32::from_le_bytes(pad(&[0x11, 0x22, 0x33, 0x44, 0x55], 0xFF)) == 0x4433_2211);
+};
+
+/// Iterator over the little endian words of `data`, for controllers that are programmed
+/// a word at a time.
+///
+/// The bytes are copied, so `data` does not need to be aligned. If its length is not a
+/// multiple of the word size the last word is padded with `fill`, which should be the
+/// erased value of the flash so the padding leaves the bytes after `data` unchanged.
+///
+/// ```ignore
+/// for (i, word) in Words::<u32>::new(data, 0xFF).enumerate() {
+///     write_volatile((address + 4 * i as u32) as *mut u32, word);
+/// }
+/// ```
+pub struct Words<'a, W> {
+    data: &'a [u8],
+    fill: u8,
+    word: PhantomData<W>,
+}
+
+impl<'a, W: Word> Words<'a, W> {
+    pub fn new(data: &'a [u8], fill: u8) -> Self {
+        Self {
+            data,
+            fill,
+            word: PhantomData,
+        }
+    }
+}
+
+impl<'a, W> Words<'a, W> {
+    /// The bytes of the next word, which are fewer than a word at the end of `data`.
+    const fn next_chunk(&mut self) -> Option<&'a [u8]> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let len = match self.data.len() < core::mem::size_of::<W>() {
+            true => self.data.len(),
+            false => core::mem::size_of::<W>(),
+        };
+        let (chunk, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(chunk)
+    }
+}
+
+impl<W: Word> Iterator for Words<'_, W> {
+    type Item = W;
+
+    fn next(&mut self) -> Option<W> {
+        let chunk = self.next_chunk()?;
+        Some(W::from_partial_le(chunk, self.fill))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.data.len().div_ceil(core::mem::size_of::<W>());
+        (len, Some(len))
+    }
+}
+
+impl<W: Word> ExactSizeIterator for Words<'_, W> {}
+
+// Every whole word is taken as is, the tail word is padded with the fill byte and the
+// iteration ends after it.
+const _: () = {
+    const DATA: [u8; 7] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+
+    const fn word(words: &mut Words<u32>) -> Option<u32> {
+        match words.next_chunk() {
+            Some(chunk) => Some(u32::from_le_bytes(pad(chunk, words.fill))),
+            None => None,
+        }
+    }
+    const fn is(word: Option<u32>, expected: u32) -> bool {
+        matches!(word, Some(word) if word == expected)
+    }
+
+    let mut words = Words::<u32> {
+        data: DATA.split_at(1).0,
+        fill: 0xFF,
+        word: PhantomData,
+    };
+    assert!(is(word(&mut words), 0xFFFF_FF11));
+    assert!(word(&mut words).is_none());
+
+    let mut words = Words::<u32> {
+        data: DATA.split_at(3).0,
+        fill: 0xFF,
+        word: PhantomData,
+    };
+    assert!(is(word(&mut words), 0xFF33_2211));
+    assert!(word(&mut words).is_none());
+
+    let mut words = Words::<u32> {
+        data: DATA.split_at(5).0,
+        fill: 0x00,
+        word: PhantomData,
+    };
+    assert!(is(word(&mut words), 0x4433_2211));
+    assert!(is(word(&mut words), 0x0000_0055));
+    assert!(word(&mut words).is_none());
+
+    let mut words = Words::<u32> {
+        data: &DATA,
+        fill: 0xFF,
+        word: PhantomData,
+    };
+    assert!(is(word(&mut words), 0x4433_2211));
+    assert!(is(word(&mut words), 0xFF77_6655));
+    assert!(word(&mut words).is_none());
+    assert!(word(&mut words).is_none());
+};